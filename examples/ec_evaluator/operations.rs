@@ -17,6 +17,10 @@ fn tag_checker(name: &'static str, tag: &'static str) -> Procedure {
     Procedure::new(name, 1, move |args| is_tagged_list(&args[0], tag))
 }
 
+fn form_checker(name: &'static str, form: SpecialForm) -> Procedure {
+    Procedure::new(name, 1, move |args| special_form(&args[0]) == Some(form))
+}
+
 fn let_to_combination(args: Vec<Value>) -> Vec<Value> {
     // `(let ((<var_1> <exp_1>) ... (<var_n> <exp_n>)) <body>)`
     // or "Named `let`": `(let <var> <bindings> <body>)`
@@ -115,12 +119,12 @@ pub fn operations() -> Vec<Procedure> {
     operations.push(Procedure::new("application?", 1, |args| {
         !args[0].is_empty_list()
     }));
-    operations.push(tag_checker("quoted?", "quote"));
-    operations.push(tag_checker("assignment?", "set!"));
-    operations.push(tag_checker("definition?", "define"));
-    operations.push(tag_checker("if?", "if"));
-    operations.push(tag_checker("lambda?", "lambda"));
-    operations.push(tag_checker("begin?", "begin"));
+    operations.push(form_checker("quoted?", SpecialForm::Quote));
+    operations.push(form_checker("assignment?", SpecialForm::Assignment));
+    operations.push(form_checker("definition?", SpecialForm::Definition));
+    operations.push(form_checker("if?", SpecialForm::If));
+    operations.push(form_checker("lambda?", SpecialForm::Lambda));
+    operations.push(form_checker("begin?", SpecialForm::Begin));
     operations.push(Procedure::new("make-procedure", 3, |args| {
         let mut proc = args[..3].to_vec();
         proc.insert(0, Value::new("procedure"));
@@ -174,7 +178,7 @@ pub fn operations() -> Vec<Procedure> {
         definition_value(&args[0])
     }));
     // support `cond` statement
-    operations.push(tag_checker("cond?", "cond"));
+    operations.push(form_checker("cond?", SpecialForm::Cond));
     operations.push(Procedure::duplicate(&cdr, "cond-clauses"));
     operations.push(Procedure::duplicate(&car, "first-clause"));
     operations.push(Procedure::duplicate(&is_last_one, "last-clause?"));
@@ -183,10 +187,10 @@ pub fn operations() -> Vec<Procedure> {
     operations.push(Procedure::duplicate(&car, "clause-predicate"));
     operations.push(tag_checker("else-clause?", "else"));
     // support `let` statement, as a syntactic sugar
-    operations.push(tag_checker("let?", "let"));
+    operations.push(form_checker("let?", SpecialForm::Let));
     operations.push(Procedure::new("let->combination", 1, let_to_combination));
     // support `let*` statement, as a syntactic sugar
-    operations.push(tag_checker("let*?", "let*"));
+    operations.push(form_checker("let*?", SpecialForm::LetStar));
     operations.push(Procedure::new("let*->nested-lets", 1, |args| {
         // `(let* ((<var_1> <exp_1>) ... (<var_n> <exp_n>)) <body>)`
         let exp = Vec::<Value>::try_from(&args[0]).unwrap();
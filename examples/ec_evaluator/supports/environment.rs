@@ -27,7 +27,7 @@ impl Environment {
     }
 
     fn lookup(&self, args: &[Value]) -> Value {
-        if args.len() < 1 {
+        if args.is_empty() {
             panic!("[LOOKUP] Missing a variable name.");
         }
         let var = args[0].to_string();
@@ -90,7 +90,7 @@ impl Environment {
         }
     }
 
-    fn extend_inner_map(&self, vars: &Vec<Value>, vals: &Vec<Value>) {
+    fn extend_inner_map(&self, vars: &[Value], vals: &[Value]) {
         let mut env = self.0.lock().unwrap();
         env.extend(
             vars.iter()
@@ -1,24 +1,19 @@
 use std::io::{self, prelude::*};
 
-use fancy_regex::Regex;
-use lazy_static::lazy_static;
 use log::debug;
 use reg_machine::{machine::value::Value, parser::rml_value, rmlvalue_to_value};
 
 use super::{list::list_ref, syntax::is_compound_procedure};
 
-/// Read from Stdin and replace `'` to `quote`.
+/// Read from Stdin, `'` is parsed as `quote`.
 /// Supports multiple lines.
 pub fn read() -> Value {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"'(\([^'\)]*\)|\w+)+(?!')").unwrap();
-    }
     let mut balance = 0;
     let mut result = String::new();
     let mut previous = 0u8 as char;
 
     // Read multiple lines and balance parentheses.
-    for b in io::stdin().bytes() {
+    for b in io::stdin().lock().bytes() {
         let mut c = b.unwrap() as char;
         if c == '(' {
             balance += 1;
@@ -38,7 +33,7 @@ pub fn read() -> Value {
     }
 
     debug!("read result: {}", result);
-    let (_, res) = rml_value(&RE.replace_all(&result, "(quote $1)")).unwrap();
+    let (_, res) = rml_value(&result).unwrap();
     rmlvalue_to_value(&res)
 }
 
@@ -5,11 +5,7 @@ pub fn list_ref(val: &Value, index: usize) -> Value {
     let article = if index == 0 { "the first" } else { "an" };
     if let Value::List(l) = val {
         debug!("list_ref: {:?}[{}]", l, index);
-        let list: Vec<Value> = l
-            .iter()
-            .map(|v| v.clone())
-            .filter(|v| !v.is_nil())
-            .collect();
+        let list: Vec<Value> = l.iter().filter(|v| !v.is_nil()).cloned().collect();
         if list.len() < index + 1 {
             panic!(
                 "The object (), passed as {} argument to {}car, is not the correct type.",
@@ -32,11 +28,7 @@ pub fn list_rest(val: &Value, start: usize) -> Value {
     let article = if start == 1 { "the first" } else { "an" };
     if let Value::List(l) = val {
         debug!("list_rest: {:?}[{}..]", l, start);
-        let list: Vec<Value> = l
-            .iter()
-            .map(|v| v.clone())
-            .filter(|v| !v.is_nil())
-            .collect();
+        let list: Vec<Value> = l.iter().filter(|v| !v.is_nil()).cloned().collect();
         if list.len() < start {
             panic!(
                 "The object (), passed as {} argument to {}cdr, is not the correct type.",
@@ -46,10 +38,8 @@ pub fn list_rest(val: &Value, start: usize) -> Value {
         }
         let rest = l[start..].to_vec();
         debug!("rest: {:?}", rest);
-        if !rest.is_empty() {
-            if l.len() != list.len() {
-                return rest[0].clone();
-            }
+        if !rest.is_empty() && l.len() != list.len() {
+            return rest[0].clone();
         }
         Value::new(rest)
     } else {
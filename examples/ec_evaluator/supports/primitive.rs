@@ -42,13 +42,15 @@ pub fn primitive_procedures() -> Vec<Procedure> {
     procedures.push(Procedure::new("+", 0, math::addition));
     procedures.push(Procedure::new("-", 1, math::subtraction));
     procedures.push(Procedure::new("*", 0, math::multiplication));
-    procedures.push(Procedure::new("/", 1, math::division));
+    procedures.push(Procedure::fallible("/", 1, math::division));
     procedures.push(Procedure::new("=", 0, math::equal));
     procedures.push(Procedure::new("<", 0, math::less_than));
     procedures.push(Procedure::new(">", 0, math::greater_than));
     procedures.push(Procedure::new("<=", 0, math::less_than_or_equal_to));
     procedures.push(Procedure::new(">=", 0, math::greater_than_or_equal_to));
-    procedures.push(make_proc!("exit", |_| std::process::exit(0)));
+    procedures.push(Procedure::new("exit", 0, |_| -> Value {
+        std::process::exit(0)
+    }));
     procedures.push(make_proc!("display", 1, |v: Value| display(&v)));
     procedures.push(make_proc!("newline", |_| println!()));
     // Support logical composition operations: `and`, `or` and `not`.
@@ -71,10 +73,7 @@ pub fn primitive_procedures() -> Vec<Procedure> {
         }
         false.to_value()
     }));
-    procedures.push(Procedure::new("not", 1, |args| {
-        if args.len() > 1 {
-            panic!("The procedure #[not] has been called with {} arguments; it requires exactly 1 argument.", args.len());
-        }
+    procedures.push(Procedure::new_ranged("not", 1, 1, |args| {
         args[0].is_false()
     }));
     procedures.push(Procedure::new("list", 0, |args| args.to_value()));
@@ -10,6 +10,45 @@ pub fn is_tagged_list(val: &Value, tag: &str) -> bool {
     }
 }
 
+/// Special forms the evaluator dispatches on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpecialForm {
+    Quote,
+    Assignment,
+    Definition,
+    If,
+    Cond,
+    Lambda,
+    Let,
+    LetStar,
+    Begin,
+}
+
+/// Classifies an expression by the symbol at its head with a single match,
+/// returns `None` if it isn't a special form.
+pub fn special_form(val: &Value) -> Option<SpecialForm> {
+    if let Value::List(l) = val {
+        let head = match l.first() {
+            Some(Value::Symbol(head)) => head,
+            _ => return None,
+        };
+        match head.as_str() {
+            "quote" => Some(SpecialForm::Quote),
+            "set!" => Some(SpecialForm::Assignment),
+            "define" => Some(SpecialForm::Definition),
+            "if" => Some(SpecialForm::If),
+            "cond" => Some(SpecialForm::Cond),
+            "lambda" => Some(SpecialForm::Lambda),
+            "let" => Some(SpecialForm::Let),
+            "let*" => Some(SpecialForm::LetStar),
+            "begin" => Some(SpecialForm::Begin),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
 pub fn is_compound_procedure(val: &Value) -> bool {
     is_tagged_list(val, "procedure")
 }
@@ -70,6 +109,28 @@ mod syntax_tests {
         ));
     }
 
+    #[test]
+    fn test_special_form() {
+        let forms = vec![
+            ("(quote a)", SpecialForm::Quote),
+            ("(set! a b)", SpecialForm::Assignment),
+            ("(define a b)", SpecialForm::Definition),
+            ("(if (condition) consequent)", SpecialForm::If),
+            ("(cond (else a))", SpecialForm::Cond),
+            ("(lambda (a b) c)", SpecialForm::Lambda),
+            ("(let ((a 1)) a)", SpecialForm::Let),
+            ("(let* ((a 1)) a)", SpecialForm::LetStar),
+            ("(begin (set! a b) a)", SpecialForm::Begin),
+        ];
+        for (exp, form) in forms.into_iter() {
+            assert_eq!(Some(form), special_form(&parse(exp)));
+        }
+        assert_eq!(None, special_form(&parse("(inc a)")));
+        assert_eq!(None, special_form(&parse("((lambda (a) a) 1)")));
+        assert_eq!(None, special_form(&parse("quote")));
+        assert_eq!(None, special_form(&parse("()")));
+    }
+
     #[test]
     fn test_if_alternative() {
         assert_eq!(
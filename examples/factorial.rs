@@ -17,11 +17,11 @@ const CONTROLLER_TEXT: &str = r#"
 "#;
 
 fn procedures() -> Vec<Procedure> {
-    let mut procedures: Vec<Procedure> = vec![];
-    procedures.push(Procedure::new(">", 2, math::greater_than));
-    procedures.push(Procedure::new("*", 2, math::multiplication));
-    procedures.push(Procedure::new("+", 2, math::addition));
-    procedures
+    vec![
+        Procedure::new(">", 2, math::greater_than),
+        Procedure::new("*", 2, math::multiplication),
+        Procedure::new("+", 2, math::addition),
+    ]
 }
 
 fn main() {
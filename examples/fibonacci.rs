@@ -1,7 +1,4 @@
-use reg_machine::{
-    machine::{procedure::Procedure, value::TryFromValue},
-    make_machine, math,
-};
+use reg_machine::{machine::procedure::Procedure, make_machine, math};
 
 const CONTROLLER_TEXT: &str = r#"
 (controller
@@ -53,9 +50,7 @@ fn procedures() -> Vec<Procedure> {
     let mut procedures: Vec<Procedure> = vec![];
     procedures.push(Procedure::new("newline", 0, |_| println!()));
     procedures.push(Procedure::new("eq?", 2, math::equal));
-    procedures.push(Procedure::new("noninteger?", 1, |args| {
-        i32::try_from(&args[0]).is_err()
-    }));
+    procedures.push(Procedure::new("noninteger?", 1, math::noninteger));
     procedures.push(Procedure::new("<", 2, math::less_than));
     procedures.push(Procedure::new("+", 2, math::addition));
     procedures.push(Procedure::new("-", 2, math::subtraction));
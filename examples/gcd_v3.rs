@@ -22,17 +22,17 @@ const CONTROLLER_TEXT: &str = r#"
 "#;
 
 fn procedures() -> Vec<Procedure> {
-    let mut procedures: Vec<Procedure> = vec![];
-    procedures.push(Procedure::new("=", 2, math::equal));
-    procedures.push(Procedure::new("<", 2, math::less_than));
-    procedures.push(Procedure::new("-", 2, math::subtraction));
-    procedures
+    vec![
+        Procedure::new("=", 2, math::equal),
+        Procedure::new("<", 2, math::less_than),
+        Procedure::new("-", 2, math::subtraction),
+    ]
 }
 
 fn main() {
     let register_names = vec!["a", "b", "t"];
     let procedures = procedures();
-    let mut machine = make_machine(register_names, &procedures, &CONTROLLER_TEXT).unwrap();
+    let mut machine = make_machine(register_names, &procedures, CONTROLLER_TEXT).unwrap();
     machine.set_register_content("a", 1023).unwrap();
     machine.set_register_content("b", 27).unwrap();
     assert_eq!(Ok("Done"), machine.start());
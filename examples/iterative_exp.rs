@@ -20,11 +20,11 @@ const CONTROLLER_TEXT: &str = r#"
 "#;
 
 fn procedures() -> Vec<Procedure> {
-    let mut procedures: Vec<Procedure> = vec![];
-    procedures.push(Procedure::new("=", 2, math::equal));
-    procedures.push(Procedure::new("-", 2, math::subtraction));
-    procedures.push(Procedure::new("*", 2, math::multiplication));
-    procedures
+    vec![
+        Procedure::new("=", 2, math::equal),
+        Procedure::new("-", 2, math::subtraction),
+        Procedure::new("*", 2, math::multiplication),
+    ]
 }
 
 fn main() {
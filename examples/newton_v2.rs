@@ -30,7 +30,7 @@ fn procedures() -> Vec<Procedure> {
     procedures.push(Procedure::new("+", 2, math::addition));
     procedures.push(Procedure::new("-", 2, math::subtraction));
     procedures.push(Procedure::new("*", 2, math::multiplication));
-    procedures.push(Procedure::new("/", 2, math::division));
+    procedures.push(Procedure::fallible("/", 2, math::division));
     procedures.push(Procedure::new("<", 2, math::less_than));
     procedures.push(Procedure::new("abs", 1, |args| {
         let x = f64::try_from(&args[0]).unwrap();
@@ -42,6 +42,6 @@ fn procedures() -> Vec<Procedure> {
 fn main() {
     let register_names = vec!["g", "t", "x"];
     let procedures = procedures();
-    let mut machine = make_machine(register_names, &procedures, &CONTROLLER_TEXT).unwrap();
+    let mut machine = make_machine(register_names, &procedures, CONTROLLER_TEXT).unwrap();
     assert_eq!(Ok("Done"), machine.start());
 }
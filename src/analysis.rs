@@ -0,0 +1,303 @@
+//! Static checks over the parsed controller, run before the machine starts.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::parser::{RMLNode, RMLValue};
+
+/// A possible problem in the controller, found without running it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    /// The label of the block where the problem was found.
+    pub label: String,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.label, self.message)
+    }
+}
+
+/// How serious a diagnostic is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The controller may misbehave.
+    Warning,
+    /// The controller will fail when the instruction is executed.
+    Error,
+}
+
+/// A problem found by `Machine::validate`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The index of the instruction in the controller, if the problem belongs to one.
+    pub index: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.index {
+            Some(index) => write!(f, "{} at {:04}: {}", severity, index, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+/// The operation arguments, including the ones of the nested operations.
+fn operation_args<'a>(args: impl IntoIterator<Item = &'a RMLNode>) -> Vec<&'a RMLNode> {
+    args.into_iter()
+        .flat_map(|arg| match arg {
+            RMLNode::Operation(_, args) => operation_args(args),
+            arg => vec![arg],
+        })
+        .collect()
+}
+
+/// The label names referenced by the instruction.
+pub(crate) fn referenced_labels(inst: &RMLNode) -> Vec<&str> {
+    let targets: Vec<&RMLNode> = match inst {
+        RMLNode::Assignment(_, op) | RMLNode::PerformOp(op) | RMLNode::TestOp(op) => match &**op {
+            RMLNode::Operation(_, args) => operation_args(args),
+            value => vec![value],
+        },
+        RMLNode::Branch(label) | RMLNode::GotoLabel(label) | RMLNode::TailCall(label) => {
+            vec![label]
+        }
+        RMLNode::GotoIfEq(lhs, rhs, label) => {
+            [operation_args([&**lhs, &**rhs]), vec![label]].concat()
+        }
+        RMLNode::IfFlag(consequent, alternative) => vec![consequent, alternative],
+        _ => vec![],
+    };
+    targets
+        .into_iter()
+        .filter_map(|node| match node {
+            RMLNode::Label(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The operations applied by the instruction, as `(name, argument count)`,
+/// the outermost one first, followed by the nested ones.
+pub(crate) fn applied_operations(inst: &RMLNode) -> Vec<(&str, usize)> {
+    fn operations(node: &RMLNode) -> Vec<(&str, usize)> {
+        match node {
+            RMLNode::Operation(name, args) => std::iter::once((name.as_str(), args.len()))
+                .chain(args.iter().flat_map(operations))
+                .collect(),
+            _ => vec![],
+        }
+    }
+    match inst {
+        RMLNode::Assignment(_, op) | RMLNode::PerformOp(op) | RMLNode::TestOp(op) => operations(op),
+        RMLNode::GotoIfEq(lhs, rhs, _) => [operations(lhs), operations(rhs)].concat(),
+        _ => vec![],
+    }
+}
+
+/// The registers whose contents are read by the instruction.
+pub(crate) fn read_registers(inst: &RMLNode) -> Vec<&str> {
+    fn operand(node: &RMLNode) -> Vec<&str> {
+        match node {
+            RMLNode::Reg(name) => vec![name.as_str()],
+            RMLNode::Operation(_, args) => args.iter().flat_map(operand).collect(),
+            _ => vec![],
+        }
+    }
+    match inst {
+        RMLNode::Assignment(_, value) => operand(value),
+        RMLNode::Dispatch(reg) => [operand(reg), vec!["argl"]].concat(),
+        RMLNode::GotoLabel(label) => operand(label),
+        RMLNode::GotoIfEq(lhs, rhs, _) => [operand(lhs), operand(rhs)].concat(),
+        RMLNode::PerformOp(op) | RMLNode::TestOp(op) => operand(op),
+        RMLNode::Save(name) => vec![name.as_str()],
+        _ => vec![],
+    }
+}
+
+/// The register written by the instruction.
+pub(crate) fn written_register(inst: &RMLNode) -> Option<&str> {
+    match inst {
+        RMLNode::Assignment(name, _) | RMLNode::Restore(name) => Some(name.as_str()),
+        RMLNode::Dispatch(_) => Some("val"),
+        _ => None,
+    }
+}
+
+/// The indices of the instructions that may be executed after the instruction at `index`.
+/// `goto` a register may continue at any label assigned to a register.
+fn successors(
+    insts: &[RMLNode],
+    index: usize,
+    label_indices: &HashMap<&str, usize>,
+    indirect: &[usize],
+) -> Vec<usize> {
+    let target = |label: &RMLNode| match label {
+        RMLNode::Label(name) | RMLNode::Constant(RMLValue::Symbol(name)) => {
+            label_indices.get(name.as_str()).cloned()
+        }
+        _ => None,
+    };
+    match &insts[index] {
+        RMLNode::GotoLabel(label) | RMLNode::TailCall(label) => match &**label {
+            RMLNode::Reg(_) => indirect.to_vec(),
+            label => target(label).into_iter().collect(),
+        },
+        RMLNode::IfFlag(consequent, alternative) => target(consequent)
+            .into_iter()
+            .chain(target(alternative))
+            .collect(),
+        RMLNode::Branch(label) | RMLNode::GotoIfEq(_, _, label) => {
+            target(label).into_iter().chain(Some(index + 1)).collect()
+        }
+        _ => vec![index + 1],
+    }
+}
+
+/// Finds the registers that may be read before being written on some execution path,
+/// as `(instruction index, register name)`, reporting each register once.
+/// `label_indices` maps the labels to the indices of their first instructions,
+/// and `initial` holds the registers written before running.
+pub(crate) fn reads_before_writes<'a>(
+    insts: &'a [RMLNode],
+    label_indices: &HashMap<&str, usize>,
+    initial: HashSet<&'a str>,
+) -> Vec<(usize, &'a str)> {
+    if insts.is_empty() {
+        return vec![];
+    }
+    let indirect: Vec<usize> = insts
+        .iter()
+        .filter_map(|inst| match inst {
+            RMLNode::Assignment(_, value) => match &**value {
+                RMLNode::Label(name) => label_indices.get(name.as_str()).cloned(),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    // The registers definitely written when reaching each instruction,
+    // `None` if the instruction isn't reached yet.
+    let mut written: Vec<Option<HashSet<&str>>> = vec![None; insts.len()];
+    written[0] = Some(initial);
+    let mut pending = vec![0];
+    while let Some(index) = pending.pop() {
+        let mut out = written[index].clone().unwrap_or_default();
+        out.extend(written_register(&insts[index]));
+        for next in successors(insts, index, label_indices, &indirect) {
+            if next >= insts.len() {
+                continue;
+            }
+            let merged = match &written[next] {
+                Some(set) => set.intersection(&out).cloned().collect(),
+                None => out.clone(),
+            };
+            if written[next].as_ref() != Some(&merged) {
+                written[next] = Some(merged);
+                pending.push(next);
+            }
+        }
+    }
+    let mut reported: HashSet<&str> = HashSet::new();
+    let mut reads = vec![];
+    for (index, inst) in insts.iter().enumerate() {
+        if let Some(set) = &written[index] {
+            for reg_name in read_registers(inst) {
+                if !set.contains(reg_name) && reported.insert(reg_name) {
+                    reads.push((index, reg_name));
+                }
+            }
+        }
+    }
+    reads
+}
+
+/// Flags the label blocks that unconditionally go to themselves
+/// without any assignment, stack operation or performed operation in between.
+/// A block that may leave through `branch`, `if-flag` or `goto-if-eq` isn't flagged.
+pub fn detect_trivial_infinite_loops(nodes: &[RMLNode]) -> Vec<Warning> {
+    let mut warnings = vec![];
+    // The labels naming the current block.
+    let mut labels: HashSet<&str> = HashSet::new();
+    // Whether the latest node was a label, so the next one names the same block.
+    let mut after_label = false;
+    // Whether the current block may change the state or leave, so the loop may end.
+    let mut may_end = false;
+    for node in nodes.iter() {
+        if let RMLNode::Symbol(label) = node {
+            if !after_label {
+                labels.clear();
+                may_end = false;
+            }
+            labels.insert(label);
+            after_label = true;
+            continue;
+        }
+        after_label = false;
+        match node {
+            RMLNode::Assignment(..)
+            | RMLNode::Save(_)
+            | RMLNode::Restore(_)
+            | RMLNode::PerformOp(_)
+            | RMLNode::Dispatch(_)
+            | RMLNode::Branch(_)
+            | RMLNode::GotoIfEq(..)
+            | RMLNode::IfFlag(..) => may_end = true,
+            RMLNode::GotoLabel(target) | RMLNode::TailCall(target) => {
+                if let RMLNode::Label(name) = &**target {
+                    if !may_end && labels.contains(name.as_str()) {
+                        warnings.push(Warning {
+                            label: name.to_string(),
+                            message: "goes to itself without changing any state".into(),
+                        });
+                    }
+                }
+                // The instructions after a goto don't belong to this loop.
+                labels.clear();
+                may_end = true;
+            }
+            _ => (),
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod analysis_tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_detect_trivial_infinite_loops() {
+        let nodes = parse(
+            r#"
+            (controller
+             wait
+               (test (op =) (reg a) (const 0))
+               (goto (label wait))
+             count
+               (assign a (op -) (reg a) (const 1))
+               (test (op =) (reg a) (const 0))
+               (branch (label done))
+               (goto (label count))
+             done)
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![Warning {
+                label: "wait".into(),
+                message: "goes to itself without changing any state".into(),
+            }],
+            detect_trivial_infinite_loops(&nodes)
+        );
+    }
+}
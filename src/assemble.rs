@@ -1,31 +1,186 @@
 use std::collections::HashMap;
 
-use crate::parser::{parse, RMLNode};
+use crate::{
+    analysis::referenced_labels,
+    parser::{controller_text, parse, RMLNode},
+};
+
+/// The instruction sequence together with the map from the labels
+/// to the indices of their first instructions.
+type Assembled = (Vec<RMLNode>, HashMap<String, usize>);
 
 /// The assemble procedure is the main entry to the assembler.
-pub fn assemble(
-    controller_text: &str,
-) -> Result<(Vec<RMLNode>, HashMap<String, Vec<RMLNode>>), String> {
-    let mut labels: HashMap<String, Vec<RMLNode>> = HashMap::new();
-    let mut insts: Vec<RMLNode> = vec![];
+/// All the labels referenced by the instructions must exist,
+/// the labels held by registers can't be known until running.
+pub fn assemble(controller_text: &str) -> Result<Assembled, String> {
+    let (insts, labels) = extract_labels(controller_text)?;
+    let mut unknown: Vec<&str> = vec![];
+    for label in insts.iter().flat_map(referenced_labels) {
+        if !labels.contains_key(label) && !unknown.contains(&label) {
+            unknown.push(label);
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(format!("[ASSEMBLE] Unknown labels: {}", unknown.join(", ")));
+    }
+    Ok((insts, labels))
+}
+
+/// Assembles like `assemble`, but collects all the problems instead of stopping at the first,
+/// i.e. a parse failure, or every duplicated label and every unknown label.
+pub fn assemble_all(controller_text: &str) -> Result<Assembled, Vec<String>> {
+    let nodes =
+        parse(controller_text).map_err(|e| vec![format!("[ASSEMBLE] Unable to parse: {}", e)])?;
+    let ((insts, labels), mut errors) = split_labels(nodes);
+    for label in insts.iter().flat_map(referenced_labels) {
+        let error = format!("[ASSEMBLE] Unknown label: {}", label);
+        if !labels.contains_key(label) && !errors.contains(&error) {
+            errors.push(error);
+        }
+    }
+    if errors.is_empty() {
+        Ok((insts, labels))
+    } else {
+        Err(errors)
+    }
+}
+
+/// Formats the assembled instructions back into a controller text like `controller_text`,
+/// with the labels starting at the same index sorted by name.
+/// Assembling the result yields the same instructions and labels.
+pub fn unparse(insts: &[RMLNode], labels: &HashMap<String, usize>) -> String {
+    let mut offsets: Vec<(usize, &str)> = labels
+        .iter()
+        .map(|(name, &index)| (index, name.as_str()))
+        .collect();
+    offsets.sort_unstable();
+    let mut offsets = offsets.into_iter().peekable();
+    let mut nodes: Vec<RMLNode> = Vec::with_capacity(insts.len() + labels.len());
+    for index in 0..=insts.len() {
+        while let Some((_, name)) = offsets.next_if(|&(offset, _)| offset == index) {
+            nodes.push(RMLNode::Symbol(name.to_string()));
+        }
+        nodes.extend(insts.get(index).cloned());
+    }
+    controller_text(&nodes)
+}
+
+/// Splits the controller into the instruction sequence and the label-offsets map,
+/// without checking the referenced labels.
+pub(crate) fn extract_labels(controller_text: &str) -> Result<Assembled, String> {
     let parse_result = parse(controller_text).unwrap();
+    match split_labels(parse_result) {
+        (assembled, errors) if errors.is_empty() => Ok(assembled),
+        (_, mut errors) => Err(errors.remove(0)),
+    }
+}
 
-    // Split the parse result into instructions set and label-insts map.
-    for node in parse_result.into_iter() {
+/// Splits the parse result into instructions set and label-offsets map,
+/// with an error for each duplicated label.
+fn split_labels(nodes: Vec<RMLNode>) -> (Assembled, Vec<String>) {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut insts: Vec<RMLNode> = vec![];
+    let mut errors: Vec<String> = vec![];
+    for node in nodes.into_iter() {
         match node {
             RMLNode::Symbol(label) => {
                 if labels.contains_key(&label) {
-                    return Err(format!("[ASSEMBLE] Duplicated label: {}", label));
-                }
-                labels.insert(label, vec![]);
-            }
-            inst => {
-                for (_, val) in labels.iter_mut() {
-                    val.push(inst.clone());
+                    let error = format!("[ASSEMBLE] Duplicated label: {}", label);
+                    if !errors.contains(&error) {
+                        errors.push(error);
+                    }
+                    continue;
                 }
-                insts.push(inst);
+                labels.insert(label, insts.len());
             }
+            inst => insts.push(inst),
         }
     }
-    Ok((insts, labels))
+    ((insts, labels), errors)
+}
+
+#[cfg(test)]
+mod assemble_tests {
+    use super::*;
+
+    #[test]
+    fn test_label_offsets() {
+        let (insts, labels) = assemble(
+            r#"
+            (controller
+             test-b
+               (test (op =) (reg b) (const 0))
+               (branch (label gcd-done))
+               (goto (label test-b))
+             gcd-done)
+            "#,
+        )
+        .unwrap();
+        assert_eq!(3, insts.len());
+        let labels: HashMap<&str, usize> = labels.iter().map(|(k, &v)| (k.as_str(), v)).collect();
+        assert_eq!(
+            vec![("controller", 0), ("gcd-done", 3), ("test-b", 0)]
+                .into_iter()
+                .collect::<HashMap<&str, usize>>(),
+            labels
+        );
+    }
+
+    #[test]
+    fn test_unknown_labels() {
+        let controller_text = r#"
+        (controller
+           (branch (label nowhere))
+           (assign continue (label done))
+           (goto (reg continue))
+           (test (op =) (reg a) (const 0))
+           (if-flag (label done) (label missing))
+           (goto (label nowhere))
+         done)
+        "#;
+        assert_eq!(
+            Err("[ASSEMBLE] Unknown labels: nowhere, missing".to_string()),
+            assemble(controller_text)
+        );
+        assert!(extract_labels(controller_text).is_ok());
+    }
+
+    #[test]
+    fn test_unparse() {
+        let (insts, labels) = assemble(crate::machine::GCD).unwrap();
+        let text = unparse(&insts, &labels);
+        assert!(text.starts_with("(controller\n test-b\n   (test (op =) (reg b) (const 0))"));
+        assert!(text.contains("\n   (assign t (op rem) (reg a) (reg b))\n"));
+        assert!(text.ends_with("\n gcd-done)"));
+        assert_eq!(Ok((insts, labels)), assemble(&text));
+    }
+
+    #[test]
+    fn test_assemble_all() {
+        let controller_text = r#"
+        (controller
+         loop
+           (goto (label nowhere))
+         done
+         loop
+           (goto (label missing))
+         done
+           (goto (label nowhere)))
+        "#;
+        assert_eq!(
+            Err(vec![
+                "[ASSEMBLE] Duplicated label: loop".to_string(),
+                "[ASSEMBLE] Duplicated label: done".to_string(),
+                "[ASSEMBLE] Unknown label: nowhere".to_string(),
+                "[ASSEMBLE] Unknown label: missing".to_string(),
+            ]),
+            assemble_all(controller_text)
+        );
+        assert_eq!(
+            Err("[ASSEMBLE] Duplicated label: loop".to_string()),
+            assemble(controller_text)
+        );
+        assert!(assemble_all("(controller (goto))").is_err());
+        assert!(assemble_all("(controller done)").is_ok());
+    }
 }
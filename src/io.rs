@@ -0,0 +1,199 @@
+//! Input and output behind the `read`, `read-number`, `read-line` and `print` procedures.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    machine::{
+        errors::{MResult, ProcedureError},
+        procedure::Procedure,
+        value::Value,
+        Machine,
+    },
+    parser::rml_value,
+    rmlvalue_to_value,
+};
+
+/// The input shared by the `read`, `read-number` and `read-line` procedures of a machine.
+pub type SharedInput = Arc<Mutex<dyn BufRead + Send>>;
+
+/// The output shared by the `print`, `write`, `read-number` and `print-stack-statistics`
+/// procedures of a machine.
+pub type SharedOutput = Arc<Mutex<dyn Write + Send>>;
+
+/// The names of the procedures installed by `install_io_procedures`.
+pub(crate) const IO_PROCEDURES: [&str; 6] = [
+    "read",
+    "read-number",
+    "read-line",
+    "print",
+    "write",
+    "print-stack-statistics",
+];
+
+/// Reads a line, failing with `ExecuteFailure` at the end of the input.
+fn next_line<R: BufRead + ?Sized>(input: &mut R) -> MResult<String> {
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) => Err(ProcedureError::ExecuteFailure("read: no more input".into()))?,
+        Ok(_) => Ok(line),
+        Err(e) => Err(ProcedureError::ExecuteFailure(format!("read: {}", e)))?,
+    }
+}
+
+/// Parses a line of input into a value, like the `read` procedure does.
+pub fn parse_input(line: &str) -> Value {
+    let (_, value) = rml_value(line.trim()).unwrap();
+    rmlvalue_to_value(&value)
+}
+
+/// Reads a line and parses it into a value.
+pub fn read<R: BufRead + ?Sized>(input: &mut R) -> MResult<Value> {
+    next_line(input).map(|line| parse_input(&line))
+}
+
+/// Reads lines until one holds a number, writing `prompt` before each read.
+pub fn read_number<R: BufRead + ?Sized, W: Write + ?Sized>(
+    input: &mut R,
+    output: &mut W,
+    prompt: &str,
+) -> MResult<Value> {
+    loop {
+        write!(output, "{}", prompt)
+            .and_then(|_| output.flush())
+            .unwrap();
+        let line = next_line(input)?;
+        if let Ok(("", value)) = rml_value(line.trim()) {
+            let value = rmlvalue_to_value(&value);
+            if value.is_num() {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+/// Reads a line as a raw string, without the line ending.
+pub fn read_line<R: BufRead + ?Sized>(input: &mut R) -> MResult<Value> {
+    let line = next_line(input)?;
+    Ok(Value::String(
+        line.trim_end_matches(&['\r', '\n'][..]).to_string(),
+    ))
+}
+
+/// Installs the `read`, `read-number`, `read-line`, `print`, `write` and
+/// `print-stack-statistics` procedures, reading from `input` and writing to `output`.
+/// `read` parses a line into a value, `read-number` reprompts until a number is entered,
+/// with the optional argument as the prompt, and `read-line` reads the raw text of a line.
+/// `print` writes strings and characters raw, and `write` their external representations.
+pub fn install_io_procedures(machine: &mut Machine, input: SharedInput, output: SharedOutput) {
+    let reader = input.clone();
+    machine.install_procedure(Procedure::fallible("read", 0, move |_| {
+        read(&mut *reader.lock().unwrap())
+    }));
+    let (reader, writer) = (input.clone(), output.clone());
+    machine.install_procedure(Procedure::fallible("read-number", 0, move |args| {
+        let prompt = args.first().cloned().map(printed_text);
+        read_number(
+            &mut *reader.lock().unwrap(),
+            &mut *writer.lock().unwrap(),
+            prompt.as_deref().unwrap_or(""),
+        )
+    }));
+    machine.install_procedure(Procedure::fallible("read-line", 0, move |_| {
+        read_line(&mut *input.lock().unwrap())
+    }));
+    let writer = output.clone();
+    machine.install_procedure(Procedure::new("print", 1, move |args| {
+        let text = printed_text(args[0].clone());
+        writeln!(writer.lock().unwrap(), "{}", text).unwrap();
+    }));
+    let writer = output.clone();
+    machine.install_procedure(Procedure::new("write", 1, move |args| {
+        writeln!(writer.lock().unwrap(), "{}", args[0].to_scheme_string()).unwrap();
+    }));
+    machine.install_procedure(Procedure::with_machine(
+        "print-stack-statistics",
+        0,
+        move |m, _| {
+            let (total_pushes, maximum_depth) = m.stack_statistics();
+            writeln!(
+                output.lock().unwrap(),
+                "\ntotal-pushes = {} maximum-depth = {}",
+                total_pushes,
+                maximum_depth
+            )
+            .unwrap();
+            Ok(Value::new("Done".to_string()))
+        },
+    ));
+}
+
+/// Makes a `read` procedure returning the given inputs in order, without reading any line,
+/// which fails with `ExecuteFailure` once the inputs are exhausted.
+/// Installed after `make_machine`, it replaces the `read` procedure reading the standard input.
+pub fn queued_reader(inputs: Vec<Value>) -> Procedure {
+    let queue = Mutex::new(VecDeque::from(inputs));
+    Procedure::fallible("read", 0, move |_| {
+        queue.lock().unwrap().pop_front().ok_or_else(|| {
+            ProcedureError::ExecuteFailure("read: no more queued inputs".into()).into()
+        })
+    })
+}
+
+/// The text of a value printed by the `print` procedure,
+/// strings are printed without quotes, and characters without the `#\` prefix.
+pub fn printed_text(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        Value::Char(c) => c.to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod io_tests {
+    use super::*;
+
+    #[test]
+    fn test_read_number() {
+        let mut input = "abc\n(1 2)\n\n42\n".as_bytes();
+        let mut output = vec![];
+        assert_eq!(
+            Ok(Value::Num(42.0)),
+            read_number(&mut input, &mut output, "n: ")
+        );
+        assert_eq!("n: n: n: n: ", String::from_utf8(output.clone()).unwrap());
+        assert_eq!(
+            Err(ProcedureError::ExecuteFailure("read: no more input".into()).into()),
+            read_number(&mut input, &mut output, "n: ")
+        );
+    }
+
+    #[test]
+    fn test_queued_reader() {
+        let mut m = crate::machine::gcd_machine(0, 0);
+        m.install_procedure(queued_reader(vec![Value::new(206), Value::new(40)]));
+        for reg_name in ["a", "b"].iter() {
+            let input = m.call_procedure("read", vec![]).unwrap();
+            m.set_register_content(*reg_name, input).unwrap();
+        }
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("a"));
+        assert_eq!(
+            Err(ProcedureError::ExecuteFailure("read: no more queued inputs".into()).into()),
+            m.call_procedure("read", vec![])
+        );
+    }
+
+    #[test]
+    fn test_read_line() {
+        let mut input = "  (not parsed) \r\nnext\n".as_bytes();
+        assert_eq!(
+            Ok(Value::String("  (not parsed) ".into())),
+            read_line(&mut input)
+        );
+        assert_eq!(Ok(Value::String("next".into())), read_line(&mut input));
+        assert!(read_line(&mut input).is_err());
+    }
+}
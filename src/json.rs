@@ -0,0 +1,285 @@
+//! Converts values to and from JSON, behind the `json` feature.
+//! The conversion is written by hand over `serde_json`,
+//! the types don't implement serde's `Serialize` or `Deserialize`.
+//!
+//! Numbers, strings, booleans and lists map to their JSON counterparts, and the empty value to `null`.
+//! The other values map to objects with a single tag key, so they stay distinguishable from strings:
+//! `{"symbol": "abc"}`, `{"char": "a"}`, `{"pointer": 3}`, and `{"procedure": "car"}` holding only the name.
+//! A promise maps to `{"opaque": "#<promise>"}`, which can't be converted back.
+//! A chain of `Cons` maps to an array, or to the opaque tag if it doesn't end in a list.
+//!
+//! A procedure converted back is a placeholder failing with `NotFound` when executed,
+//! until `Machine::restore` replaces it with the installed procedure of the same name.
+//!
+//! A `MachineState` maps to an object with the `pc`, `flag`, `stack`, `register_stacks`
+//! and `registers` keys, so a paused machine may be written to disk and resumed later.
+
+use serde_json::{json, Map, Number, Value as Json};
+
+use crate::machine::{
+    errors::{MResult, MachineError, ProcedureError},
+    procedure::Procedure,
+    value::Value,
+    MachineState,
+};
+
+fn convert_error(json: &Json) -> MachineError {
+    MachineError::ConvertError {
+        value: json.to_string(),
+        src: "JSON".into(),
+        dst: "Value".into(),
+    }
+}
+
+impl Value {
+    /// Converts the value to JSON, see the module documentation for the mapping.
+    /// A float point number that isn't finite maps to `null`.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Value::Integer(n) => Json::from(*n),
+            Value::Num(n) => Number::from_f64(*n).map_or(Json::Null, Json::Number),
+            Value::Symbol(s) => json!({ "symbol": s }),
+            Value::String(s) => Json::String(s.clone()),
+            Value::Boolean(b) => Json::Bool(*b),
+            Value::Char(c) => json!({ "char": c.to_string() }),
+            Value::List(l) => Json::Array(l.iter().map(Value::to_json).collect()),
+            Value::Cons(_) => match self.cons_parts() {
+                (items, None) => Json::Array(items.iter().map(Value::to_json).collect()),
+                (_, Some(_)) => json!({ "opaque": self.to_scheme_string() }),
+            },
+            Value::Nil => Json::Null,
+            Value::Pointer(p) => json!({ "pointer": p }),
+            Value::Procedure(p) => json!({ "procedure": p.get_name() }),
+            Value::Promise(_) => json!({ "opaque": self.to_scheme_string() }),
+        }
+    }
+
+    /// Converts JSON produced by `to_json` back to a value.
+    /// Fails with `ConvertError` for the opaque tag and the unknown objects.
+    pub fn from_json(json: &Json) -> MResult<Value> {
+        match json {
+            Json::Null => Ok(Value::Nil),
+            Json::Bool(b) => Ok(Value::Boolean(*b)),
+            Json::Number(n) => match (n.as_i64(), n.as_f64()) {
+                (Some(i), _) => Ok(Value::Integer(i)),
+                (None, Some(f)) => Ok(Value::Num(f)),
+                _ => Err(convert_error(json)),
+            },
+            Json::String(s) => Ok(Value::String(s.clone())),
+            Json::Array(items) => items.iter().map(Value::from_json).collect(),
+            Json::Object(map) => from_tagged(map).ok_or_else(|| convert_error(json)),
+        }
+    }
+}
+
+impl MachineState {
+    /// Converts the state to JSON, see the module documentation for the mapping.
+    pub fn to_json(&self) -> Json {
+        let values = |values: &[Value]| Json::Array(values.iter().map(Value::to_json).collect());
+        let register_stacks: Map<String, Json> = self
+            .register_stacks
+            .iter()
+            .map(|(name, stack)| (name.clone(), values(stack)))
+            .collect();
+        let registers: Map<String, Json> = self
+            .registers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_json()))
+            .collect();
+        json!({
+            "pc": self.pc,
+            "flag": self.flag.to_json(),
+            "stack": values(&self.stack),
+            "register_stacks": register_stacks,
+            "registers": registers,
+        })
+    }
+
+    /// Converts JSON produced by `to_json` back to a state.
+    /// Fails with `ConvertError` if a key is missing or holds an unconvertible value.
+    pub fn from_json(json: &Json) -> MResult<MachineState> {
+        let values = |json: &Json| -> MResult<Vec<Value>> {
+            json.as_array()
+                .ok_or_else(|| convert_error(json))?
+                .iter()
+                .map(Value::from_json)
+                .collect()
+        };
+        let field = |key: &str| json.get(key).ok_or_else(|| convert_error(json));
+        let object = |key: &str| field(key)?.as_object().ok_or_else(|| convert_error(json));
+        Ok(MachineState {
+            pc: field("pc")?.as_u64().ok_or_else(|| convert_error(json))? as usize,
+            flag: Value::from_json(field("flag")?)?,
+            stack: values(field("stack")?)?,
+            register_stacks: object("register_stacks")?
+                .iter()
+                .map(|(name, stack)| Ok((name.clone(), values(stack)?)))
+                .collect::<MResult<_>>()?,
+            registers: object("registers")?
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), Value::from_json(value)?)))
+                .collect::<MResult<_>>()?,
+        })
+    }
+}
+
+fn from_tagged(map: &Map<String, Json>) -> Option<Value> {
+    if map.len() != 1 {
+        return None;
+    }
+    match map.iter().next()? {
+        (tag, Json::String(s)) if tag == "symbol" => Some(Value::Symbol(s.clone())),
+        (tag, Json::String(s)) if tag == "char" && s.chars().count() == 1 => {
+            s.chars().next().map(Value::Char)
+        }
+        (tag, Json::String(name)) if tag == "procedure" => {
+            let missing = name.clone();
+            Some(Value::Procedure(Procedure::try_new(name, 0, move |_| {
+                Err(ProcedureError::NotFound(missing.clone()))?
+            })))
+        }
+        (tag, Json::Number(n)) if tag == "pointer" => {
+            n.as_u64().map(|p| Value::Pointer(p as usize))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod json_tests {
+    use super::*;
+    use crate::machine::promise::Promise;
+
+    #[test]
+    fn test_round_trip() {
+        let values = vec![
+            Value::Integer(-42),
+            Value::Num(1.5),
+            Value::Num(2.0),
+            Value::Symbol("abc".into()),
+            Value::String("abc".into()),
+            Value::Boolean(true),
+            Value::Char('a'),
+            Value::List(vec![Value::Integer(1), Value::List(vec![]), Value::Nil]),
+            Value::Nil,
+            Value::Pointer(3),
+        ];
+        for value in values.into_iter() {
+            let json = value.to_json();
+            let back = Value::from_json(&json).unwrap();
+            assert_eq!(value, back);
+            assert_eq!(value.is_integer(), back.is_integer());
+        }
+    }
+
+    #[test]
+    fn test_tagged_symbol() {
+        assert_eq!(
+            json!({ "symbol": "abc" }),
+            Value::Symbol("abc".into()).to_json()
+        );
+        assert_eq!(json!("abc"), Value::String("abc".into()).to_json());
+        assert_eq!(
+            Ok(Value::Symbol("abc".into())),
+            Value::from_json(&json!({ "symbol": "abc" }))
+        );
+        assert_eq!(
+            Ok(Value::String("abc".into())),
+            Value::from_json(&json!("abc"))
+        );
+    }
+
+    #[test]
+    fn test_unconvertible() {
+        let promise = Value::Promise(Promise::new(Procedure::new("thunk", 0, |_| Value::Nil)));
+        let json = promise.to_json();
+        assert_eq!(json!({ "opaque": "#<promise>" }), json);
+        assert!(Value::from_json(&json).is_err());
+        assert!(Value::from_json(&json!({ "symbol": "a", "pointer": 1 })).is_err());
+        assert_eq!(Json::Null, Value::Num(f64::NAN).to_json());
+    }
+
+    #[test]
+    fn test_procedure_by_name() {
+        let procedure = Value::Procedure(Procedure::new("car", 1, |_| Value::Nil));
+        let json = procedure.to_json();
+        assert_eq!(json!({ "procedure": "car" }), json);
+        match Value::from_json(&json) {
+            Ok(Value::Procedure(placeholder)) => {
+                assert_eq!("car", placeholder.get_name());
+                assert_eq!(
+                    Err(ProcedureError::NotFound("car".into()).into()),
+                    placeholder.execute(vec![])
+                );
+            }
+            other => panic!("unexpected value: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_machine_state_with_procedure() {
+        let mut m = crate::make_machine(
+            vec!["p"],
+            &crate::math::standard_procedures(),
+            "(controller (save p) (restore p))",
+        )
+        .unwrap();
+        let plus = Value::Procedure(m.procedures()["+"].clone());
+        m.set_register_content("p", plus).unwrap();
+        m.step().unwrap();
+        let json = m.snapshot().to_json();
+        assert_eq!(json!({ "procedure": "+" }), json["registers"]["p"]);
+        assert_eq!(json!([{ "procedure": "+" }]), json["stack"]);
+        let state = MachineState::from_json(&json).unwrap();
+
+        let mut fresh = m.template();
+        fresh.restore(&state).unwrap();
+        assert_eq!(m.snapshot(), fresh.snapshot());
+        match fresh.get_register_content("p") {
+            Ok(Value::Procedure(p)) => assert_eq!(
+                Ok(Value::new(3)),
+                p.execute(vec![Value::new(1), Value::new(2)])
+            ),
+            other => panic!("unexpected value: {:?}", other),
+        }
+
+        let mut bare =
+            crate::make_machine(vec!["p"], &[], "(controller (save p) (restore p))").unwrap();
+        assert_eq!(
+            Err(ProcedureError::NotFound("+".into()).into()),
+            bare.restore(&state)
+        );
+    }
+
+    #[test]
+    fn test_machine_state_round_trip() {
+        let mut m = crate::machine::gcd_machine(206, 40);
+        m.set_breakpoint("test-b");
+        m.proceed().unwrap();
+        m.proceed().unwrap();
+        let state = m.snapshot();
+        assert_eq!(Value::new(40), state.registers["a"]);
+        assert_eq!(Value::new(6), state.registers["b"]);
+        let json = state.to_json();
+        let back = MachineState::from_json(&json).unwrap();
+        assert_eq!(state, back);
+
+        m.clear_breakpoint("test-b");
+        m.start().unwrap();
+        m.restore(&back).unwrap();
+        assert_eq!(state, m.snapshot());
+        m.proceed().unwrap();
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("a"));
+
+        assert!(MachineState::from_json(&json!({ "pc": 0 })).is_err());
+
+        let mut tampered = json;
+        tampered["pc"] = json!(100);
+        let before = m.snapshot();
+        assert!(matches!(
+            m.restore(&MachineState::from_json(&tampered).unwrap()),
+            Err(MachineError::PcOutOfRange { pc: 100, .. })
+        ));
+        assert_eq!(before, m.snapshot());
+    }
+}
@@ -1,60 +1,220 @@
 mod assemble;
 
+pub mod analysis;
+pub mod io;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod list;
 pub mod machine;
 pub mod math;
 pub mod parser;
 
+use std::io::{stdin, stdout, BufReader};
+use std::sync::{Arc, Mutex};
+
 use assemble::assemble;
+pub use assemble::{assemble_all, unparse};
+use io::{SharedInput, SharedOutput};
 use machine::{
     errors::{MResult, MachineError},
     procedure::Procedure,
-    value::Value,
+    promise::Promise,
+    value::{ToValue, Value},
     Machine,
 };
-use parser::{rml_value, RMLValue};
+use parser::RMLValue;
 
 /// Constructs and returns a model of the machine with
 /// the given registers, operations, and controller.
+/// The `read` and `print` procedures use the standard input and output,
+/// see `make_machine_with_io`.
 pub fn make_machine(
     register_names: Vec<&str>,
-    procedures: &Vec<Procedure>,
+    procedures: &[Procedure],
     controller_text: &str,
+) -> MResult<Machine> {
+    make_machine_with_io(
+        register_names,
+        procedures,
+        controller_text,
+        Arc::new(Mutex::new(BufReader::new(stdin()))),
+        Arc::new(Mutex::new(stdout())),
+    )
+}
+
+/// Constructs a machine like `make_machine`, with the procedures of `io::install_io_procedures`
+/// reading from `input` and writing to `output` instead of the standard input and output.
+pub fn make_machine_with_io(
+    register_names: Vec<&str>,
+    procedures: &[Procedure],
+    controller_text: &str,
+    input: SharedInput,
+    output: SharedOutput,
 ) -> MResult<Machine> {
     let mut machine = Machine::new();
     for &reg_name in register_names.iter() {
         machine.allocate_register(reg_name)?;
     }
-    // Provides a `read` procedure to read inputs from user,
-    // and a `print` procedure to print outputs on the screen.
-    machine.install_procedure(make_proc!("read", |_| read_line_buffer()));
-    machine.install_procedure(make_proc!("print", 1, |arg: Value| match arg {
-        Value::String(s) => println!("{}", s),
-        other => println!("{}", other),
+    io::install_io_procedures(&mut machine, input, output);
+    // Provides `delay`, `force` and `cons-stream` procedures for delayed evaluation.
+    // A delayed expression is a procedure of zero arguments.
+    // A failure of the thunk is returned by `force` as an error.
+    machine.install_procedure(make_proc!(fallible "delay", 1, |thunk: Procedure| {
+        Promise::new(thunk)
     }));
+    machine
+        .install_procedure(Procedure::try_new("force", 1, |args| args[0].force()).with_max_args(1));
+    machine.install_procedure(make_proc!(
+        fallible "cons-stream",
+        2,
+        |head: Value, thunk: Procedure| vec![head, Promise::new(thunk).to_value()]
+    ));
     machine.install_procedures(procedures);
     let (insts, labels) =
         assemble(controller_text).map_err(|msg: String| MachineError::UnableAssemble(msg))?;
     machine.install_instructions(insts);
     machine.install_labels(labels);
+    machine.check_operations()?;
     Ok(machine)
 }
 
-fn read_line_buffer() -> Value {
-    // Read one line of input buffer-style
-    let mut input = String::new();
-    std::io::stdin()
-        .read_line(&mut input)
-        .expect("Failed to read line");
-    let (_, values) = rml_value(input.trim()).unwrap();
-    rmlvalue_to_value(&values)
+/// Constructs a machine like `make_machine`, with the standard arithmetic, comparison
+/// and list operations from `math::standard_procedures` and `list::standard_procedures`
+/// installed as well.
+/// The given procedures take precedence over the standard ones with the same names.
+pub fn make_machine_with_standard_ops(
+    register_names: Vec<&str>,
+    procedures: &[Procedure],
+    controller_text: &str,
+) -> MResult<Machine> {
+    let mut all_procedures = math::standard_procedures();
+    all_procedures.extend(list::standard_procedures());
+    all_procedures.extend_from_slice(procedures);
+    make_machine(register_names, &all_procedures, controller_text)
 }
 
 pub fn rmlvalue_to_value(r: &RMLValue) -> Value {
     match r {
+        RMLValue::Bool(b) => Value::Boolean(*b),
+        RMLValue::Char(c) => Value::Char(*c),
         RMLValue::Float(f) => Value::Num(*f),
-        RMLValue::Num(n) => Value::Num(*n as f64),
+        RMLValue::Num(n) => Value::Integer(*n),
         RMLValue::Str(s) => Value::String(s.to_string()),
         RMLValue::Symbol(s) => Value::Symbol(s.to_string()),
         RMLValue::List(l) => Value::List(l.iter().map(rmlvalue_to_value).collect::<Vec<Value>>()),
     }
 }
+
+/// Converts like `rmlvalue_to_value`, but a `(quote <datum>)` gives the datum itself,
+/// e.g. `'a` gives the symbol `a` instead of the list `(quote a)`.
+/// Only the outermost quote is unwrapped, the quotes within the datum are kept.
+pub fn rmlvalue_to_unquoted_value(r: &RMLValue) -> Value {
+    match r {
+        RMLValue::List(l) if l.len() == 2 && l[0] == RMLValue::Symbol("quote".into()) => {
+            rmlvalue_to_value(&l[1])
+        }
+        other => rmlvalue_to_value(other),
+    }
+}
+
+#[cfg(test)]
+mod lib_tests {
+    use super::*;
+    use crate::parser::rml_value;
+
+    fn unquoted(s: &str) -> Value {
+        let (_, result) = rml_value(s).unwrap();
+        rmlvalue_to_unquoted_value(&result)
+    }
+
+    #[test]
+    fn test_rmlvalue_to_unquoted_value() {
+        assert_eq!(Value::Symbol("a".into()), unquoted("(quote a)"));
+        assert_eq!(Value::Symbol("a".into()), unquoted("'a"));
+        assert_eq!(Value::new(vec![1, 2, 3]), unquoted("(quote (1 2 3))"));
+        assert_eq!(
+            Value::new(vec![
+                Value::Symbol("quote".into()),
+                Value::Symbol("a".into())
+            ]),
+            unquoted("''a")
+        );
+        let (_, list) = rml_value("(quote a b)").unwrap();
+        assert_eq!(rmlvalue_to_value(&list), rmlvalue_to_unquoted_value(&list));
+        let (_, list) = rml_value("(list (quote a))").unwrap();
+        assert_eq!(rmlvalue_to_value(&list), rmlvalue_to_unquoted_value(&list));
+        assert_eq!(Value::new(1), unquoted("1"));
+    }
+
+    #[test]
+    fn test_make_machine_with_io() {
+        let input = Arc::new(Mutex::new("10\n1\nq\n".as_bytes()));
+        let output = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let mut m = make_machine_with_io(
+            vec!["continue", "n", "val"],
+            &machine::fibonacci_procedures(),
+            machine::FIBONACCI_LOOP,
+            input,
+            output.clone(),
+        )
+        .unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(
+            Ok(Value::new("Done".to_string())),
+            m.call_procedure("print-stack-statistics", vec![])
+        );
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            "n: \n55\nn: \n1\nn: \n\ntotal-pushes = 352 maximum-depth = 18\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_delayed_evaluation() {
+        let controller_text = r#"
+        (controller
+           (assign p (op delay) (reg thunk))
+           (assign a (op force) (reg p))
+           (assign b (op force) (reg p))
+           (assign s (op cons-stream) (const 1) (reg thunk)))
+        "#;
+        let mut m = make_machine(vec!["thunk", "p", "a", "b", "s"], &[], controller_text).unwrap();
+        let runs = Arc::new(Mutex::new(0));
+        let counter = runs.clone();
+        let thunk = Procedure::new("thunk", 0, move |_| {
+            *counter.lock().unwrap() += 1;
+            42
+        });
+        m.set_register_content("thunk", Value::Procedure(thunk))
+            .unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(42)), m.get_register_content("a"));
+        assert_eq!(Ok(Value::new(42)), m.get_register_content("b"));
+        assert_eq!(1, *runs.lock().unwrap());
+        let stream = m.get_register_content("s").unwrap();
+        assert_eq!(Ok(Value::new(1)), stream.ref_at(0));
+        assert_eq!(
+            Ok(Value::new(42)),
+            stream.ref_at(1).and_then(|tail| tail.force())
+        );
+        assert_eq!(2, *runs.lock().unwrap());
+
+        // A failing thunk fails the run instead of panicking.
+        let failing = Procedure::fallible("failing", 0, |_| -> MResult<Value> {
+            Err(MachineError::DivisionByZero("1".into()))
+        });
+        m.set_register_content("thunk", Value::Procedure(failing))
+            .unwrap();
+        assert_eq!(Err(MachineError::DivisionByZero("1".into())), m.start());
+    }
+
+    #[test]
+    fn test_rmlvalue_to_value_char() {
+        let (_, chars) = rml_value(r"(#\a #\space)").unwrap();
+        assert_eq!(
+            Value::new(vec![Value::Char('a'), Value::Char(' ')]),
+            rmlvalue_to_value(&chars)
+        );
+    }
+}
@@ -0,0 +1,573 @@
+//! Operations over `Value::List`.
+//!
+//! The set operations treat lists as sets with structural equality,
+//! the results keep the order of first occurrence and contain no duplicates.
+//!
+//! `Value::Cons` pairs make `cons`, `car` and `cdr` O(1) by sharing the rest of the list,
+//! and convert to and from `Value::List` by `to_list` and `to_cons`.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use crate::machine::{
+    errors::{MResult, MachineError, ProcedureError, TypeError},
+    procedure::Procedure,
+    value::{Pair, ToValue, TryFromValue, Value},
+};
+
+impl Value {
+    /// The items of a list, or of a chain of pairs ending in a list.
+    fn list_items(&self) -> MResult<Cow<'_, [Value]>> {
+        match self {
+            Value::List(l) => Ok(Cow::Borrowed(l.as_slice())),
+            Value::Cons(_) => match self.cons_parts() {
+                (items, None) => Ok(Cow::Owned(items)),
+                (_, Some(_)) => Err(TypeError::expected("a proper list").got(self.to_string()))?,
+            },
+            other => Err(TypeError::expected("Value::List").got(other.to_string()))?,
+        }
+    }
+
+    /// Returns the item at `index` of the list, like `list-ref`.
+    pub fn ref_at(&self, index: usize) -> MResult<Value> {
+        let list = self.list_items()?;
+        list.get(index)
+            .cloned()
+            .ok_or(MachineError::IndexOutOfRange {
+                index,
+                len: list.len(),
+            })
+    }
+
+    /// Makes a pair of `car` and `cdr`, sharing `cdr` instead of copying it.
+    pub fn cons(car: Value, cdr: Value) -> Value {
+        Value::Cons(Arc::new(Pair(car, cdr)))
+    }
+
+    pub fn is_pair(&self) -> bool {
+        matches!(self, Value::Cons(_))
+    }
+
+    /// Returns the first item of a pair or a non-empty list.
+    pub fn car(&self) -> MResult<Value> {
+        match self {
+            Value::Cons(cell) => Ok(cell.0.clone()),
+            Value::List(l) if !l.is_empty() => Ok(l[0].clone()),
+            other => Err(TypeError::expected("Value::Cons").got(other.to_string()))?,
+        }
+    }
+
+    /// Returns the rest of a pair, shared with it, or the rest of a non-empty list, copied.
+    pub fn cdr(&self) -> MResult<Value> {
+        match self {
+            Value::Cons(cell) => Ok(cell.1.clone()),
+            Value::List(l) if !l.is_empty() => Ok(Value::List(l[1..].to_vec())),
+            other => Err(TypeError::expected("Value::Cons").got(other.to_string()))?,
+        }
+    }
+
+    /// Converts a list to a chain of pairs ending in the empty list,
+    /// the other values are returned as they are.
+    pub fn to_cons(&self) -> Value {
+        match self {
+            Value::List(l) => l.iter().rev().fold(Value::empty_list(), |cdr, car| {
+                Value::cons(car.clone(), cdr)
+            }),
+            other => other.clone(),
+        }
+    }
+
+    /// Converts a chain of pairs ending in a list to a list,
+    /// failing if it ends in anything else. The other values are returned as they are.
+    pub fn to_list(&self) -> MResult<Value> {
+        match self.cons_parts() {
+            (items, None) if self.is_pair() => Ok(Value::List(items)),
+            (_, Some(_)) if self.is_pair() => {
+                Err(TypeError::expected("a proper list").got(self.to_string()))?
+            }
+            _ => Ok(self.clone()),
+        }
+    }
+
+    /// Splits a chain of pairs into their heads and the final tail,
+    /// which is `None` if it's a list or `Value::Nil`. A list ending the chain is spliced in.
+    pub(crate) fn cons_parts(&self) -> (Vec<Value>, Option<Value>) {
+        let mut items = vec![];
+        let mut rest = self;
+        loop {
+            match rest {
+                Value::Cons(cell) => {
+                    items.push(cell.0.clone());
+                    rest = &cell.1;
+                }
+                Value::List(l) => {
+                    items.extend(l.iter().cloned());
+                    return (items, None);
+                }
+                Value::Nil => return (items, None),
+                tail => return (items, Some(tail.clone())),
+            }
+        }
+    }
+
+    /// Returns the rest of the list starting from `index`, like `list-tail`.
+    pub fn rest_from(&self, index: usize) -> MResult<Value> {
+        let list = self.list_items()?;
+        list.get(index..)
+            .map(|rest| Value::List(rest.to_vec()))
+            .ok_or(MachineError::IndexOutOfRange {
+                index,
+                len: list.len(),
+            })
+    }
+}
+
+/// Splits the list into `len` items, followed by the rest of the list if `rest` is true.
+/// Used by `destructure!`.
+#[doc(hidden)]
+pub fn destructure(value: &Value, pattern: &str, len: usize, rest: bool) -> MResult<Vec<Value>> {
+    match value {
+        Value::List(l) if l.len() == len || (rest && l.len() > len) => {
+            let mut items = l[..len].to_vec();
+            if rest {
+                items.push(Value::List(l[len..].to_vec()));
+            }
+            Ok(items)
+        }
+        _ => Err(MachineError::DestructureFailure {
+            value: value.to_string(),
+            pattern: pattern.to_string(),
+        }),
+    }
+}
+
+/// Destructures a `Value::List` into a tuple of values, with a Scheme-like pattern:
+/// `(a b)` matches a list of exactly two items,
+/// and `(head . tail)` matches a non-empty list, `tail` being the list of the rest items.
+/// The names only document the items, and the result is a `MResult`.
+#[macro_export]
+macro_rules! destructure {
+    ( $value:expr, ($($name:ident)+ . $rest:ident) ) => {
+        $crate::destructure!(@split $value, ($($name)+ . $rest), [$($name)+ $rest], true)
+    };
+    ( $value:expr, ($($name:ident)*) ) => {
+        $crate::destructure!(@split $value, ($($name)*), [$($name)*], false)
+    };
+    ( @split $value:expr, $pattern:tt, [$($name:ident)*], $rest:expr ) => {{
+        let len = [$(stringify!($name)),*].len() - if $rest { 1 } else { 0 };
+        $crate::list::destructure(&$value, stringify!($pattern), len, $rest).map(|items| {
+            let mut items = items.into_iter();
+            ($({
+                let _ = stringify!($name);
+                items.next().unwrap()
+            },)*)
+        })
+    }};
+}
+
+fn list_items<'a>(op: &str, items: &'a [Value], index: usize) -> Cow<'a, [Value]> {
+    match items.get(index).map(Value::list_items) {
+        Some(Ok(l)) => l,
+        Some(Err(_)) => panic!("[{}] Requires a list, got {}.", op, items[index]),
+        None => panic!("[{}] Requires {} lists.", op, index + 1),
+    }
+}
+
+fn dedup(items: impl IntoIterator<Item = Value>) -> Vec<Value> {
+    let mut result: Vec<Value> = vec![];
+    for item in items.into_iter() {
+        if !result.contains(&item) {
+            result.push(item);
+        }
+    }
+    result
+}
+
+pub fn remove_duplicates(items: Vec<Value>) -> Value {
+    let list = list_items("REMOVE-DUPLICATES", &items, 0);
+    Value::List(dedup(list.iter().cloned()))
+}
+
+pub fn union(items: Vec<Value>) -> Value {
+    let set1 = list_items("UNION", &items, 0);
+    let set2 = list_items("UNION", &items, 1);
+    Value::List(dedup(set1.iter().chain(set2.iter()).cloned()))
+}
+
+pub fn intersection(items: Vec<Value>) -> Value {
+    let set1 = list_items("INTERSECTION", &items, 0);
+    let set2 = list_items("INTERSECTION", &items, 1);
+    Value::List(dedup(set1.iter().filter(|v| set2.contains(v)).cloned()))
+}
+
+pub fn difference(items: Vec<Value>) -> Value {
+    let set1 = list_items("DIFFERENCE", &items, 0);
+    let set2 = list_items("DIFFERENCE", &items, 1);
+    Value::List(dedup(set1.iter().filter(|v| !set2.contains(v)).cloned()))
+}
+
+/// The rest of the list starting at the first item equal to the given item,
+/// or `#f` if there is none, like Scheme's `member`.
+pub fn member(items: Vec<Value>) -> Value {
+    let list = list_items("MEMBER", &items, 1);
+    match list.iter().position(|v| *v == items[0]) {
+        Some(index) => Value::List(list[index..].to_vec()),
+        None => Value::Boolean(false),
+    }
+}
+
+/// Whether the list contains the given item, the `member?` operation.
+pub fn is_member(items: Vec<Value>) -> Value {
+    let list = list_items("MEMBER?", &items, 1);
+    Value::Boolean(list.contains(&items[0]))
+}
+
+/// The item nested in the list at the path of indices, the `path-ref` operation,
+/// e.g. `tree[0][1]` for the path `(0 1)`.
+/// Fails with `IndexOutOfRange`, or a `TypeError` if the path goes into a non-list
+/// or an index isn't a whole number.
+pub fn path_ref(items: Vec<Value>) -> MResult<Value> {
+    if items.len() < 2 {
+        Err(ProcedureError::ArgsTooFew {
+            name: "path-ref".into(),
+            expected: 2,
+            got: items.len(),
+        })?;
+    }
+    let path = items[1].list_items()?;
+    path.iter().try_fold(items[0].clone(), |value, index| {
+        let index: usize = match index {
+            Value::Num(n) if n.fract() != 0.0 => {
+                Err(TypeError::expected("a whole number index").got(index.to_string()))?
+            }
+            _ => TryFromValue::try_from(index)?,
+        };
+        value.ref_at(index)
+    })
+}
+
+/// Pairs up the items of two lists, truncating to the shorter one.
+pub fn zip(items: Vec<Value>) -> Value {
+    let list1 = list_items("ZIP", &items, 0);
+    let list2 = list_items("ZIP", &items, 1);
+    list1
+        .iter()
+        .zip(list2.iter())
+        .map(|(a, b)| Value::List(vec![a.clone(), b.clone()]))
+        .collect()
+}
+
+/// Pairs up the items of the list with their indices, as `(index item)`.
+pub fn enumerate(items: Vec<Value>) -> Value {
+    let list = list_items("ENUMERATE", &items, 0);
+    list.iter()
+        .enumerate()
+        .map(|(i, item)| Value::List(vec![i.to_value(), item.clone()]))
+        .collect()
+}
+
+/// Sorts the list in ascending order, the items must be comparable with each other.
+pub fn sort(items: Vec<Value>) -> Value {
+    let mut list = list_items("SORT", &items, 0).into_owned();
+    list.sort_by(|a, b| {
+        a.partial_cmp(b)
+            .unwrap_or_else(|| panic!("[SORT] Unable to compare {} with {}.", a, b))
+    });
+    Value::List(list)
+}
+
+/// The list operations installed as procedures.
+pub fn standard_procedures() -> Vec<Procedure> {
+    vec![
+        Procedure::new("member", 2, member),
+        Procedure::new("member?", 2, is_member),
+        Procedure::fallible("path-ref", 2, path_ref),
+    ]
+}
+
+#[cfg(test)]
+mod list_tests {
+    use super::*;
+    use crate::{parser::rml_value, rmlvalue_to_value};
+
+    fn parse(s: &str) -> Value {
+        let (_, result) = rml_value(s).unwrap();
+        rmlvalue_to_value(&result)
+    }
+
+    #[test]
+    fn test_ref_at() {
+        let list = parse("(1 a 2)");
+        assert_eq!(Ok(Value::Num(1.0)), list.ref_at(0));
+        assert_eq!(Ok(Value::Num(2.0)), list.ref_at(2));
+        assert_eq!(
+            Err(MachineError::IndexOutOfRange { index: 3, len: 3 }),
+            list.ref_at(3)
+        );
+        assert!(matches!(
+            parse("a").ref_at(0),
+            Err(MachineError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_rest_from() {
+        let list = parse("(1 a 2)");
+        assert_eq!(Ok(parse("(a 2)")), list.rest_from(1));
+        assert_eq!(Ok(parse("()")), list.rest_from(3));
+        assert_eq!(
+            Err(MachineError::IndexOutOfRange { index: 4, len: 3 }),
+            list.rest_from(4)
+        );
+        assert!(matches!(
+            parse("a").rest_from(1),
+            Err(MachineError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_cons() {
+        let list = parse("(1 a 2)").to_cons();
+        assert!(list.is_pair());
+        assert_eq!(Ok(Value::new(1)), list.car());
+        let rest = list.cdr().unwrap();
+        // The rest is shared with the pair instead of copied.
+        match (&list, &rest) {
+            (Value::Cons(cell), Value::Cons(rest_cell)) => match &cell.1 {
+                Value::Cons(shared) => assert!(Arc::ptr_eq(shared, rest_cell)),
+                other => panic!("unexpected cdr: {:?}", other),
+            },
+            other => panic!("unexpected pairs: {:?}", other),
+        }
+        assert_eq!(parse("(a 2)"), rest);
+        assert_eq!(parse("(1 a 2)"), list);
+        assert_eq!(list, parse("(1 a 2)"));
+        assert_ne!(parse("(1 a)"), list);
+        assert_eq!(Ok(parse("(1 a 2)")), list.to_list());
+        assert!(list.to_list().unwrap().is_list());
+        assert_eq!(parse("(0 1 a 2)"), Value::cons(Value::new(0), list.clone()));
+        assert_eq!(parse("(0 1 2)"), Value::cons(Value::new(0), parse("(1 2)")));
+        assert!(parse("()").car().is_err());
+    }
+
+    #[test]
+    fn test_display_cons() {
+        let list = parse("(1 \"a\" (b))").to_cons();
+        assert_eq!(parse("(1 \"a\" (b))").to_string(), list.to_string());
+        assert_eq!("(1 \"a\" (b))", list.to_scheme_string());
+        let pair = Value::cons(Value::new(1), Value::cons(Value::new(2), Value::new(3)));
+        assert_eq!("(1 2 . 3)", pair.to_string());
+        assert_eq!("(1 2 . 3)", pair.to_scheme_string());
+        assert!(pair.to_list().is_err());
+        assert_ne!(parse("(1 2 3)"), pair);
+    }
+
+    #[test]
+    fn test_member() {
+        let list = parse("(1 (a b) c)");
+        assert_eq!(
+            parse("((a b) c)"),
+            member(vec![parse("(a b)"), list.clone()])
+        );
+        assert_eq!(
+            Value::Boolean(true),
+            is_member(vec![parse("c"), list.clone()])
+        );
+        assert_eq!(
+            Value::Boolean(false),
+            member(vec![parse("a"), list.clone()])
+        );
+        assert_eq!(Value::Boolean(false), is_member(vec![parse("a"), list]));
+        assert_eq!(Value::Boolean(false), member(vec![parse("a"), parse("()")]));
+        assert_eq!(
+            Value::Boolean(false),
+            is_member(vec![parse("a"), parse("()")])
+        );
+    }
+
+    #[test]
+    fn test_member_operations() {
+        let controller_text = r#"
+        (controller
+           (assign rest (op member) (const b) (const (a b c)))
+           (assign missing (op member) (const d) (const (a b c)))
+           (test (op member?) (const c) (reg rest)))
+        "#;
+        let mut m = crate::make_machine(
+            vec!["rest", "missing"],
+            &standard_procedures(),
+            controller_text,
+        )
+        .unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(parse("(b c)")), m.get_register_content("rest"));
+        assert_eq!(Ok(Value::Boolean(false)), m.get_register_content("missing"));
+        assert_eq!(Value::Boolean(true), m.flag());
+    }
+
+    #[test]
+    fn test_destructure() {
+        let (a, b) = destructure!(parse("(1 (a b))"), (a b)).unwrap();
+        assert_eq!(Value::Num(1.0), a);
+        assert_eq!(parse("(a b)"), b);
+        let (head, tail) = destructure!(parse("(1 2 3)"), (head.tail)).unwrap();
+        assert_eq!(Value::Num(1.0), head);
+        assert_eq!(parse("(2 3)"), tail);
+        let (head, tail) = destructure!(parse("(1)"), (head.tail)).unwrap();
+        assert_eq!(Value::Num(1.0), head);
+        assert_eq!(parse("()"), tail);
+        assert_eq!(
+            Err(MachineError::DestructureFailure {
+                value: "(1 2 3)".into(),
+                pattern: "(a b)".into(),
+            }),
+            destructure!(parse("(1 2 3)"), (a b))
+        );
+        assert!(destructure!(parse("()"), (head.tail)).is_err());
+        assert!(destructure!(parse("a"), (a)).is_err());
+    }
+
+    #[test]
+    fn test_cons_chains() {
+        let list = parse("(3 (a b) 1)").to_cons();
+        assert_eq!(Ok(parse("(a b)")), list.ref_at(1));
+        assert_eq!(Ok(parse("(1)")), list.rest_from(2));
+        assert_eq!(
+            parse("((a b) 1)"),
+            member(vec![parse("(a b)"), list.clone()])
+        );
+        assert_eq!(
+            Ok(parse("b")),
+            path_ref(vec![list.clone(), parse("(1 1)").to_cons()])
+        );
+        assert_eq!(parse("(1 3)"), sort(vec![parse("(3 1)").to_cons()]));
+        assert_eq!(
+            parse("((3 1))"),
+            zip(vec![parse("(3)").to_cons(), parse("(1 2)")])
+        );
+        assert_eq!(
+            parse("(3 1)"),
+            intersection(vec![list.clone(), parse("(1 3)").to_cons()])
+        );
+        assert!(matches!(
+            Value::cons(Value::Num(1.0), Value::Num(2.0)).ref_at(0),
+            Err(MachineError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_drop_long_cons_chain() {
+        let chain = (0..1_000_000).fold(Value::empty_list(), |cdr, i| {
+            Value::cons(Value::Num(i as f64), cdr)
+        });
+        drop(chain);
+    }
+
+    #[test]
+    fn test_path_ref() {
+        let tree = parse("((1 (a b)) 2)");
+        assert_eq!(
+            Ok(parse("(a b)")),
+            path_ref(vec![tree.clone(), parse("(0 1)")])
+        );
+        assert_eq!(
+            Ok(parse("b")),
+            path_ref(vec![tree.clone(), parse("(0 1 1)")])
+        );
+        assert_eq!(Ok(tree.clone()), path_ref(vec![tree.clone(), parse("()")]));
+        assert_eq!(
+            Err(MachineError::IndexOutOfRange { index: 2, len: 2 }),
+            path_ref(vec![tree.clone(), parse("(0 2)")])
+        );
+        assert!(matches!(
+            path_ref(vec![tree.clone(), parse("(1 0)")]),
+            Err(MachineError::TypeError(_))
+        ));
+        assert!(matches!(
+            path_ref(vec![tree.clone(), Value::new(vec![0.0, 1.5])]),
+            Err(MachineError::TypeError(_))
+        ));
+        assert!(matches!(
+            path_ref(vec![tree.clone(), Value::List(vec![Value::Integer(-1)])]),
+            Err(MachineError::TypeError(_))
+        ));
+        assert_eq!(
+            Ok(parse("(a b)")),
+            path_ref(vec![tree.clone(), Value::new(vec![0.0, 1.0])])
+        );
+        assert_eq!(
+            Err(MachineError::ProcedureError(ProcedureError::ArgsTooFew {
+                name: "path-ref".into(),
+                expected: 2,
+                got: 1,
+            })),
+            path_ref(vec![tree])
+        );
+    }
+
+    #[test]
+    fn test_sort() {
+        assert_eq!(parse("(1 2 3)"), sort(vec![parse("(3 1 2)")]));
+        assert_eq!(
+            parse("(apple banana cherry)"),
+            sort(vec![parse("(cherry apple banana)")])
+        );
+        assert_eq!(
+            parse("((1 2) (1 3) (2))"),
+            sort(vec![parse("((2) (1 3) (1 2))")])
+        );
+        assert_eq!(parse("()"), sort(vec![parse("()")]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_sort_requires_comparable_items() {
+        sort(vec![parse("(1 a 2)")]);
+    }
+
+    #[test]
+    fn test_zip() {
+        assert_eq!(
+            parse("((1 a) (2 b))"),
+            zip(vec![parse("(1 2 3)"), parse("(a b)")])
+        );
+        assert_eq!(parse("()"), zip(vec![parse("()"), parse("(a b)")]));
+    }
+
+    #[test]
+    fn test_enumerate() {
+        assert_eq!(parse("((0 x) (1 y))"), enumerate(vec![parse("(x y)")]));
+        assert_eq!(parse("()"), enumerate(vec![parse("()")]));
+    }
+
+    #[test]
+    fn test_remove_duplicates() {
+        assert_eq!(
+            parse("(1 a 2 b)"),
+            remove_duplicates(vec![parse("(1 a 1 2 a b 2)")])
+        );
+        assert_eq!(parse("()"), remove_duplicates(vec![parse("()")]));
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let set1 = parse("(1 a 2 b)");
+        let set2 = parse("(b 3 1 c)");
+        assert_eq!(
+            parse("(1 a 2 b 3 c)"),
+            union(vec![set1.clone(), set2.clone()])
+        );
+        assert_eq!(
+            parse("(1 b)"),
+            intersection(vec![set1.clone(), set2.clone()])
+        );
+        assert_eq!(parse("(a 2)"), difference(vec![set1.clone(), set2]));
+        assert_eq!(parse("()"), intersection(vec![set1, parse("()")]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_operation_requires_lists() {
+        union(vec![parse("(1 2)"), parse("a")]);
+    }
+}
@@ -27,6 +27,32 @@ pub enum MachineError {
     UnableAssemble(String),
     #[error("Stack error: {0}.")]
     StackError(String),
+    #[error("Stack overflow: the depth {depth} has reached the limit.")]
+    StackOverflow { depth: usize },
+    #[error("Cannot divide {0} by zero.")]
+    DivisionByZero(String),
+    #[error("Integer overflow: {0}.")]
+    IntegerOverflow(String),
+    #[error("Index {index} is out of range for a list of length {len}.")]
+    IndexOutOfRange { index: usize, len: usize },
+    #[error("Instruction {pc} is out of range for a controller of {len} instructions.")]
+    PcOutOfRange { pc: usize, len: usize },
+    #[error("Not a tail call to {label}: stack depth {got}, expected {expected}.")]
+    NotTailCall {
+        label: String,
+        expected: i32,
+        got: i32,
+    },
+    #[error("Fell off the end of the controller without going to a label at the end.")]
+    FellOffEnd,
+    #[error("Operation {0} didn't finish in time.")]
+    OperationTimeout(String),
+    #[error("Stopped after executing {0} instructions.")]
+    StepLimitExceeded(usize),
+    #[error("Failed to write the trace: {0}")]
+    TraceFailure(String),
+    #[error("Unable to destructure {value} as {pattern}.")]
+    DestructureFailure { value: String, pattern: String },
 }
 
 pub type MResult<T> = std::result::Result<T, MachineError>;
@@ -76,6 +102,14 @@ pub enum ProcedureError {
         expected: usize,
         got: usize,
     },
+    #[error("Procedure {name} expected at most {max} arguments, got {got}.")]
+    ArgsTooMany {
+        name: String,
+        max: usize,
+        got: usize,
+    },
+    #[error("Unknown operations: {}", .0.join(", "))]
+    UnknownOperations(Vec<String>),
     #[error("Expected a procedure to be performed, got {0}")]
     UnablePerform(String),
 }
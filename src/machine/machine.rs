@@ -1,7 +1,10 @@
 //! The register machine
 
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Cursor, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use log::{debug, info, trace, warn};
 
@@ -10,56 +13,453 @@ use super::{
     procedure::Procedure,
     register::Register,
     stack::Stack,
-    value::{values_to_str, ToValue, Value},
+    value::{values_to_str, IntegerOverflowMode, ToValue, Value},
 };
-use crate::{parser::RMLNode, rmlvalue_to_value};
+use crate::{
+    analysis::{applied_operations, reads_before_writes, referenced_labels, Diagnostic, Severity},
+    io, math,
+    parser::{RMLNode, RMLValue},
+    rmlvalue_to_value,
+};
+
+/// How many trace lines are written between two flushes of the trace writer.
+const TRACE_FLUSH_INTERVAL: u64 = 1024;
+
+/// How `Machine::proceed` stopped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunState {
+    /// No instructions are left to be executed.
+    Done,
+    /// The pc reached the breakpoint at `label`, before executing its first instruction.
+    Paused { label: String },
+}
+
+/// Statistics of the last run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunStats {
+    /// Total instructions executed.
+    pub instructions: u64,
+    /// Total pushes onto the stack since it was initialized.
+    pub total_pushes: i32,
+    /// Maximum stack depth since it was initialized.
+    pub maximum_depth: i32,
+    /// Total jumps taken by `goto`, `branch` and `if-flag`.
+    pub jumps: u64,
+    /// Wall-clock duration of the run.
+    pub duration: Duration,
+}
+
+/// Executed instructions of the last run, counted by kind.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InstructionStats {
+    pub assign: u64,
+    pub branch: u64,
+    pub dispatch: u64,
+    pub goto: u64,
+    pub goto_if_eq: u64,
+    pub if_flag: u64,
+    pub perform: u64,
+    pub restore: u64,
+    pub save: u64,
+    pub tailcall: u64,
+    pub test: u64,
+}
+
+impl InstructionStats {
+    /// Total instructions executed.
+    pub fn total(&self) -> u64 {
+        self.assign
+            + self.branch
+            + self.dispatch
+            + self.goto
+            + self.goto_if_eq
+            + self.if_flag
+            + self.perform
+            + self.restore
+            + self.save
+            + self.tailcall
+            + self.test
+    }
+
+    fn count(&mut self, inst: &RMLNode) {
+        let counter = match inst {
+            RMLNode::Assignment(..) => &mut self.assign,
+            RMLNode::Branch(_) => &mut self.branch,
+            RMLNode::Dispatch(_) => &mut self.dispatch,
+            RMLNode::GotoIfEq(..) => &mut self.goto_if_eq,
+            RMLNode::GotoLabel(_) => &mut self.goto,
+            RMLNode::IfFlag(..) => &mut self.if_flag,
+            RMLNode::PerformOp(_) => &mut self.perform,
+            RMLNode::Restore(_) => &mut self.restore,
+            RMLNode::Save(_) => &mut self.save,
+            RMLNode::TailCall(_) => &mut self.tailcall,
+            RMLNode::TestOp(_) => &mut self.test,
+            _ => return,
+        };
+        *counter += 1;
+    }
+}
+
+/// A snapshot of the execution state, the pc, flag, stack and registers,
+/// to be resumed by `Machine::restore_continuation`.
+/// Cloning is cheap, and a continuation may be restored any number of times.
+#[derive(Clone)]
+pub struct Continuation {
+    state: Arc<ContinuationState>,
+}
+
+struct ContinuationState {
+    pc: Register,
+    flag: Register,
+    stack: Stack,
+    register_stacks: HashMap<String, Stack>,
+    register_table: HashMap<String, Register>,
+}
+
+/// The execution state as plain values, captured by `Machine::snapshot`
+/// and resumed by `Machine::restore`, without the procedures.
+/// Unlike a `Continuation`, it may be inspected, compared between runs,
+/// or converted to JSON with the `json` feature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineState {
+    pub pc: usize,
+    pub flag: Value,
+    /// The stack contents, from the bottom to the top.
+    pub stack: Vec<Value>,
+    /// The contents of the per-register stacks, see `Machine::use_per_register_stacks`.
+    pub register_stacks: HashMap<String, Vec<Value>>,
+    /// The contents of the registers, without `pc` and `flag`.
+    pub registers: HashMap<String, Value>,
+}
+
+/// A view of the machine state passed to the trace hook, before executing an instruction.
+pub struct MachineSnapshot<'a> {
+    machine: &'a Machine,
+}
+
+impl MachineSnapshot<'_> {
+    /// The index of the instruction about to be executed.
+    pub fn pc(&self) -> usize {
+        match self.machine.pc.get() {
+            Value::Pointer(pc) => pc,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn flag(&self) -> Value {
+        self.machine.flag()
+    }
+
+    /// The contents of the register, `None` if there is no such register.
+    pub fn register(&self, reg_name: &str) -> Option<Value> {
+        self.machine.register_table.get(reg_name).map(Register::get)
+    }
+
+    pub fn stack_depth(&self) -> i32 {
+        self.machine.stack_depth()
+    }
+}
+
+/// Called by the machine before executing each instruction, see `Machine::set_trace_hook`.
+pub type TraceHook = Box<dyn FnMut(&RMLNode, &MachineSnapshot) + Send>;
+
+/// Called with the name, the old and the new contents of each write to a traced register,
+/// see `Machine::trace_register`.
+pub type RegisterTraceHook = Box<dyn FnMut(&str, &Value, &Value) + Send>;
+
+/// Replaces the procedures in the value, including the ones nested in lists and pairs,
+/// with the installed procedures of the same names, failing with `NotFound` for the others.
+fn resolve_procedures(procedures: &HashMap<String, Procedure>, value: &Value) -> MResult<Value> {
+    let resolve = |value: &Value| resolve_procedures(procedures, value);
+    match value {
+        Value::Procedure(procedure) => procedures
+            .get(&procedure.get_name())
+            .cloned()
+            .map(Value::Procedure)
+            .ok_or_else(|| ProcedureError::NotFound(procedure.get_name()).into()),
+        Value::List(items) => items.iter().map(resolve).collect(),
+        Value::Cons(_) => {
+            let (items, tail) = value.cons_parts();
+            let tail = tail.as_ref().map_or(Ok(Value::Nil), resolve)?;
+            items
+                .iter()
+                .rev()
+                .try_fold(tail, |rest, item| Ok(Value::cons(resolve(item)?, rest)))
+        }
+        other => Ok(other.clone()),
+    }
+}
+
+/// The name of the label node, `None` for the other nodes.
+fn label_name(node: &RMLNode) -> Option<&str> {
+    match node {
+        RMLNode::Label(name) => Some(name.as_str()),
+        _ => None,
+    }
+}
 
 pub struct Machine {
     pc: Register,
     flag: Register,
     stack: Stack,
+    /// The stacks of the registers, used instead of `stack` by `use_per_register_stacks`.
+    register_stacks: HashMap<String, Stack>,
+    per_register_stacks: bool,
     the_inst_seq: Vec<RMLNode>,
-    the_labels: HashMap<String, Vec<RMLNode>>,
+    inst_counts: Vec<u64>,
+    inst_stats: InstructionStats,
+    jump_count: u64,
+    jump_target_counts: HashMap<String, u64>,
+    operation_counts: HashMap<String, u64>,
+    run_duration: Duration,
+    run_start: Option<Instant>,
+    /// Maps the labels to the indices of their first instructions.
+    the_labels: HashMap<String, usize>,
     the_procedures: HashMap<String, Procedure>,
     register_table: HashMap<String, Register>,
+    /// The stack depth at the first tail call of each label since the label was entered otherwise.
+    tail_call_depths: HashMap<String, i32>,
+    check_tail_calls: bool,
+    require_explicit_halt: bool,
+    trace_writer: Option<Box<dyn Write + Send>>,
+    /// The labels sorted by their start indices, to name the traced instructions.
+    trace_labels: Vec<(usize, String)>,
+    traced_lines: u64,
+    /// Called with the new pc whenever the pc changes.
+    pc_hook: Option<Box<dyn FnMut(usize) + Send>>,
+    breakpoints: HashSet<String>,
+    /// The pc where `proceed` paused, to resume from it without pausing again.
+    paused_pc: Option<usize>,
+    trace_hook: Option<TraceHook>,
+    traced_registers: HashSet<String>,
+    register_trace_hook: Option<RegisterTraceHook>,
+    /// The values written to the tracked registers during the last run, in order.
+    register_histories: HashMap<String, Vec<Value>>,
+    /// The states before the latest steps, the latest one last.
+    history: VecDeque<Continuation>,
+    /// How many states are kept in the history, no history if zero.
+    history_depth: usize,
+    /// How long an operation may run, see `set_operation_timeout`.
+    operation_timeout: Option<Duration>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Machine {
     pub fn new() -> Self {
-        Self {
+        let mut machine = Self {
             pc: Register::new(),
             flag: Register::new(),
             stack: Stack::new(),
+            register_stacks: HashMap::new(),
+            per_register_stacks: false,
             the_inst_seq: Vec::new(),
+            inst_counts: Vec::new(),
+            inst_stats: InstructionStats::default(),
+            jump_count: 0,
+            jump_target_counts: HashMap::new(),
+            operation_counts: HashMap::new(),
+            run_duration: Duration::default(),
+            run_start: None,
             the_labels: HashMap::new(),
             the_procedures: HashMap::new(),
             register_table: HashMap::new(),
-        }
+            tail_call_depths: HashMap::new(),
+            check_tail_calls: true,
+            require_explicit_halt: false,
+            trace_writer: None,
+            trace_labels: Vec::new(),
+            traced_lines: 0,
+            pc_hook: None,
+            breakpoints: HashSet::new(),
+            paused_pc: None,
+            trace_hook: None,
+            traced_registers: HashSet::new(),
+            register_trace_hook: None,
+            register_histories: HashMap::new(),
+            history: VecDeque::new(),
+            history_depth: 0,
+            operation_timeout: None,
+        };
+        machine.install_builtin_procedures();
+        machine
+    }
+
+    /// Constructs a machine with preallocated register and procedure tables.
+    pub fn with_capacity(registers: usize, procedures: usize) -> Self {
+        let mut machine = Self::new();
+        machine.the_procedures.reserve(procedures);
+        machine.register_table.reserve(registers);
+        machine
+    }
+
+    /// Makes a fresh machine running the same controller, without parsing it again.
+    /// The instructions, labels, procedures, registers and settings are copied,
+    /// the procedures sharing their functions, while the contents of the registers,
+    /// the stack, the statistics, the hooks and the breakpoints start anew.
+    pub fn template(&self) -> Self {
+        let mut machine = Self {
+            the_inst_seq: self.the_inst_seq.clone(),
+            inst_counts: vec![0; self.the_inst_seq.len()],
+            the_labels: self.the_labels.clone(),
+            the_procedures: self.the_procedures.clone(),
+            register_table: self
+                .register_table
+                .keys()
+                .map(|name| (name.clone(), Register::new()))
+                .collect(),
+            per_register_stacks: self.per_register_stacks,
+            check_tail_calls: self.check_tail_calls,
+            require_explicit_halt: self.require_explicit_halt,
+            operation_timeout: self.operation_timeout,
+            ..Self::new()
+        };
+        machine
+            .stack
+            .set_max_depth_limit(self.stack.max_depth_limit());
+        machine.reset_pc();
+        machine
+    }
+
+    /// Installs the procedures handled by the machine itself.
+    fn install_builtin_procedures(&mut self) {
+        let done = || Ok(Value::new("Done".to_string()));
+        // The elapsed time since the machine started, in milliseconds.
+        self.install_procedure(Procedure::with_machine("current-time", 0, |m, _| {
+            let elapsed = m.run_start.map(|start| start.elapsed());
+            Ok(elapsed.unwrap_or_default().to_value())
+        }));
+        // With per-register stacks, the top of the stack of the register named by the argument.
+        self.install_procedure(
+            Procedure::with_machine("stack-top", 0, |m, args| {
+                let stack = if m.per_register_stacks {
+                    let reg_name = match args.first() {
+                        Some(Value::Symbol(name)) | Some(Value::String(name)) => name,
+                        _ => {
+                            return Err(MachineError::StackError(
+                                "stack-top needs a register name with per-register stacks".into(),
+                            ))
+                        }
+                    };
+                    m.register_stacks.get(reg_name).ok_or_else(|| {
+                        MachineError::StackError(format!("no saved contents of {}", reg_name))
+                    })?
+                } else {
+                    &m.stack
+                };
+                stack
+                    .peek()
+                    .cloned()
+                    .map_err(|s| MachineError::StackError(s.to_string()))
+            })
+            .with_max_args(1),
+        );
+        self.install_procedure(Procedure::with_machine(
+            "initialize-stack",
+            0,
+            move |m, _| {
+                m.initialize_stack();
+                done()
+            },
+        ));
+        // Prints to the standard output, until replaced by `io::install_io_procedures`.
+        self.install_procedure(Procedure::with_machine(
+            "print-stack-statistics",
+            0,
+            move |m, _| {
+                m.print_stack_statistics();
+                done()
+            },
+        ));
     }
 
     fn initialize_stack(&mut self) {
         self.stack.initialize();
+        self.register_stacks.clear();
+        self.tail_call_depths.clear();
     }
 
     fn print_stack_statistics(&self) {
-        self.stack.print_statistics();
+        let (total_pushes, maximum_depth) = self.stack_statistics();
+        println!(
+            "\ntotal-pushes = {} maximum-depth = {}",
+            total_pushes, maximum_depth
+        );
+    }
+
+    /// The total pushes and the maximum depth of the stack.
+    /// With per-register stacks, the pushes onto all of them,
+    /// and the maximum depth of the deepest one.
+    pub(crate) fn stack_statistics(&self) -> (i32, i32) {
+        if self.per_register_stacks {
+            self.register_stacks
+                .values()
+                .fold((0, 0), |(pushes, depth), stack| {
+                    (
+                        pushes + stack.total_pushes(),
+                        depth.max(stack.maximum_depth()),
+                    )
+                })
+        } else {
+            (self.stack.total_pushes(), self.stack.maximum_depth())
+        }
+    }
+
+    /// The current depth of the stack, or the sum of the per-register stacks.
+    fn stack_depth(&self) -> i32 {
+        if self.per_register_stacks {
+            self.register_stacks
+                .values()
+                .map(Stack::current_depth)
+                .sum()
+        } else {
+            self.stack.current_depth()
+        }
+    }
+
+    /// Makes `save` and `restore` use a separate stack for each register,
+    /// as in SICP exercise 5.11 c, so restoring a register not saved fails.
+    /// The shared stack is used by default. Switching clears the stacks.
+    pub fn use_per_register_stacks(&mut self, enabled: bool) {
+        self.per_register_stacks = enabled;
+        self.initialize_stack();
     }
 
     pub fn install_procedure(&mut self, proc: Procedure) {
         self.the_procedures.insert(proc.get_name(), proc);
     }
 
-    pub fn install_procedures(&mut self, procedures: &Vec<Procedure>) {
+    pub fn install_procedures(&mut self, procedures: &[Procedure]) {
         self.the_procedures.extend(
             procedures
-                .into_iter()
+                .iter()
                 .map(|proc| (proc.get_name(), proc.clone())),
         );
     }
 
+    /// Installs the procedures, overriding the installed ones with the same names,
+    /// and the later ones win if `others` has duplicated names.
+    /// Logs a warning for each overridden procedure, and returns their names.
+    pub fn merge_procedures(&mut self, others: &[Procedure]) -> Vec<String> {
+        let mut overridden = vec![];
+        for proc in others.iter() {
+            let name = proc.get_name();
+            if self.the_procedures.contains_key(&name) {
+                warn!("override procedure: {}", name);
+                overridden.push(name.to_string());
+            }
+            self.the_procedures.insert(name, proc.clone());
+        }
+        overridden
+    }
+
     pub fn allocate_register<S: Into<String>>(&mut self, name: S) -> MResult<&'static str> {
         let name = name.into();
-        if name.eq("pc") && name.eq("flag") && self.register_table.contains_key(&name) {
+        if name == "pc" || name == "flag" || self.register_table.contains_key(&name) {
             Err(RegisterError::AllocateFailure(name))?
         } else {
             self.register_table.insert(name, Register::new());
@@ -88,7 +488,18 @@ impl Machine {
         let reg_name = reg_name.into();
         if let Some(reg) = self.register_table.get_mut(&reg_name) {
             debug!("set reg: {} to val: {}", reg_name, value);
-            reg.set(value.to_value());
+            let value = value.to_value();
+            if self.traced_registers.contains(&reg_name) {
+                let old = reg.get();
+                info!("register {}: {} -> {}", reg_name, old, value);
+                if let Some(hook) = self.register_trace_hook.as_mut() {
+                    hook(&reg_name, &old, &value);
+                }
+            }
+            if let Some(history) = self.register_histories.get_mut(&reg_name) {
+                history.push(value.clone());
+            }
+            reg.set(value);
             Ok("Done")
         } else {
             warn!("unknown register: {}", reg_name);
@@ -96,104 +507,888 @@ impl Machine {
         }
     }
 
+    /// Returns the sorted names of the allocated registers, without `pc` and `flag`.
+    pub fn register_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.register_table.keys().cloned().collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Snapshots the contents of every register, including `pc` and `flag` under their names,
+    /// which `allocate_register` never gives to other registers.
+    pub fn dump_registers(&self) -> HashMap<String, Value> {
+        let mut dump: HashMap<String, Value> = self
+            .register_table
+            .iter()
+            .map(|(name, reg)| (name.clone(), reg.get()))
+            .collect();
+        dump.insert("pc".into(), self.pc.get());
+        dump.insert("flag".into(), self.flag.get());
+        dump
+    }
+
+    /// Copies the contents of the named registers into the same registers of `dst`,
+    /// e.g. to feed the output of one machine to another.
+    /// Fails without copying anything if either machine lacks a register.
+    pub fn copy_registers_into(&self, dst: &mut Machine, names: &[&str]) -> MResult<()> {
+        let mut contents = Vec::with_capacity(names.len());
+        for &name in names.iter() {
+            if !dst.register_table.contains_key(name) {
+                warn!("unknown register: {}", name);
+                Err(RegisterError::LookupFailure(name.to_string()))?;
+            }
+            contents.push(self.get_register_content(name)?);
+        }
+        for (&name, value) in names.iter().zip(contents) {
+            dst.set_register_content(name, value)?;
+        }
+        Ok(())
+    }
+
     pub fn total_registers(&self) -> usize {
         self.register_table.len() + 2
     }
 
     pub fn total_procedures(&self) -> usize {
-        self.the_procedures.len() + 2
+        self.the_procedures.len()
+    }
+
+    /// Returns the sorted names of all available operations,
+    /// including the builtin `current-time`, `initialize-stack`, `print-stack-statistics`
+    /// and `stack-top`.
+    pub fn operation_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.the_procedures.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Checks whether an operation named `name` is available.
+    pub fn has_operation(&self, name: &str) -> bool {
+        self.the_procedures.contains_key(name)
     }
 
     pub fn call_procedure<S: Into<String>>(&mut self, name: S, args: Vec<Value>) -> MResult<Value> {
         trace!("call a procedure");
         let name = name.into();
-        let res = Ok(Value::new("Done".to_string()));
-        match name.as_str() {
-            "initialize-stack" => {
-                debug!("call a builtin procedure: initialize-stack");
-                self.initialize_stack();
-                res
-            }
-            "print-stack-statistics" => {
-                debug!("call a builtin procedure: print-stack-statistics");
-                self.print_stack_statistics();
-                res
-            }
-            _ => {
-                debug!(
-                    "call a procedure: {} with args: {}",
-                    name,
-                    values_to_str(&args)
-                );
-                self.the_procedures.get(&name).map_or_else(
-                    || Err(ProcedureError::NotFound(name).into()),
-                    |op| op.execute(args),
-                )
-            }
+        *self.operation_counts.entry(name.clone()).or_insert(0) += 1;
+        debug!(
+            "call a procedure: {} with args: {}",
+            name,
+            values_to_str(&args)
+        );
+        match self.the_procedures.get(&name) {
+            Some(op) if op.needs_machine() => op.clone().execute_with(self, args),
+            Some(op) => match self.operation_timeout {
+                Some(timeout) => execute_with_timeout(op.clone(), args, timeout),
+                None => op.execute(args),
+            },
+            None => Err(ProcedureError::NotFound(name).into()),
         }
     }
 
+    /// Fails an operation with `OperationTimeout` if it runs longer than `timeout`,
+    /// `None` removes the limit.
+    /// With a timeout, every operation call spawns a thread to run on,
+    /// and a timed-out one keeps running in the background until it returns.
+    /// The operations receiving the machine, like `initialize-stack`, aren't limited.
+    pub fn set_operation_timeout(&mut self, timeout: Option<Duration>) {
+        self.operation_timeout = timeout;
+    }
+
+    /// The shared stack, which stays empty with per-register stacks, see `register_stack`.
     pub fn stack(&self) -> &Stack {
         &self.stack
     }
 
+    /// The stack of the register with per-register stacks,
+    /// `None` if the register hasn't been saved since the stacks were initialized.
+    pub fn register_stack(&self, reg_name: &str) -> Option<&Stack> {
+        self.register_stacks.get(reg_name)
+    }
+
+    /// Limits the stack depth, a `save` beyond the limit fails with `StackOverflow`.
+    /// The stack is unlimited by default.
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.stack.set_max_depth_limit(Some(limit));
+    }
+
     pub fn procedures(&self) -> &HashMap<String, Procedure> {
         &self.the_procedures
     }
 
     pub fn install_instructions(&mut self, insts: Vec<RMLNode>) {
+        self.inst_counts = vec![0; insts.len()];
         self.the_inst_seq = insts;
+        self.reset_pc();
+    }
+
+    /// Returns how many times each instruction was executed during the last run,
+    /// indexed by the instruction position in the controller.
+    pub fn instruction_counts(&self) -> &[u64] {
+        &self.inst_counts
+    }
+
+    /// Returns whether each instruction was executed at least once during the last run.
+    pub fn coverage(&self) -> Vec<(usize, bool)> {
+        self.inst_counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| (i, count > 0))
+            .collect()
+    }
+
+    /// Returns the percentage of instructions executed during the last run.
+    pub fn coverage_percentage(&self) -> f64 {
+        if self.inst_counts.is_empty() {
+            return 100.0;
+        }
+        let covered = self.inst_counts.iter().filter(|&&count| count > 0).count();
+        covered as f64 * 100.0 / self.inst_counts.len() as f64
+    }
+
+    /// Maps the labels to the indices of their first instructions in the instruction sequence.
+    fn label_indices(&self) -> HashMap<&str, usize> {
+        self.the_labels
+            .iter()
+            .map(|(name, &index)| (name.as_str(), index))
+            .collect()
+    }
+
+    /// Returns a numbered listing of the instruction sequence,
+    /// with the labels annotated at their start indices.
+    pub fn disassemble(&self) -> String {
+        let total = self.the_inst_seq.len();
+        let mut labels: Vec<(usize, &str)> = self
+            .label_indices()
+            .into_iter()
+            .map(|(name, index)| (index, name))
+            .collect();
+        labels.sort_unstable();
+        let mut labels = labels.into_iter().peekable();
+        let mut listing = String::new();
+        for index in 0..=total {
+            while let Some((_, name)) = labels.next_if(|(start, _)| *start == index) {
+                listing.push_str(&format!("{}:\n", name));
+            }
+            if let Some(inst) = self.the_inst_seq.get(index) {
+                let inst = inst.to_string();
+                // Drops the outermost parentheses, like an assembly listing.
+                listing.push_str(&format!("{:04}  {}\n", index, &inst[1..inst.len() - 1]));
+            }
+        }
+        listing
+    }
+
+    /// Renders the control-flow graph of the controller as a Graphviz DOT graph.
+    /// The nodes are the labels, and the edges are the jumps and the fall-throughs between them,
+    /// `goto` a register may go to any label once assigned to the register.
+    pub fn to_dot(&self) -> String {
+        let total = self.the_inst_seq.len();
+        let mut labels: Vec<(usize, &str)> = self
+            .label_indices()
+            .into_iter()
+            .map(|(name, index)| (index, name))
+            .collect();
+        labels.sort_unstable();
+        let mut lines = vec!["digraph controller {".to_string()];
+        let mut edges: Vec<(&str, &str, String)> = vec![];
+        for (i, &(start, name)) in labels.iter().enumerate() {
+            lines.push(format!("    \"{}\";", name));
+            let next = labels.get(i + 1);
+            let end = next.map_or(total, |&(index, _)| index);
+            let mut add_edge = |to: Option<_>, kind: String| {
+                if let Some(to) = to {
+                    edges.push((name, to, kind));
+                }
+            };
+            let mut falls_through = true;
+            for inst in self.the_inst_seq[start..end].iter() {
+                match inst {
+                    RMLNode::Branch(label) => add_edge(label_name(label), "branch".into()),
+                    RMLNode::GotoIfEq(_, _, label) => {
+                        add_edge(label_name(label), "goto-if-eq".into())
+                    }
+                    RMLNode::IfFlag(consequent, alternative) => {
+                        add_edge(label_name(consequent), "if-flag".into());
+                        add_edge(label_name(alternative), "if-flag".into());
+                    }
+                    RMLNode::TailCall(label) => add_edge(label_name(label), "tailcall".into()),
+                    RMLNode::GotoLabel(label) => match &**label {
+                        RMLNode::Reg(reg_name) => {
+                            for inst in self.the_inst_seq.iter() {
+                                if let RMLNode::Assignment(target, value) = inst {
+                                    if target == reg_name {
+                                        add_edge(
+                                            label_name(value),
+                                            format!("goto (reg {})", reg_name),
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                        label => add_edge(label_name(label), "goto".into()),
+                    },
+                    _ => continue,
+                }
+                if let RMLNode::GotoLabel(_) | RMLNode::IfFlag(..) | RMLNode::TailCall(_) = inst {
+                    falls_through = false;
+                    break;
+                }
+            }
+            if let (true, Some(&(_, next_name))) = (falls_through, next) {
+                edges.push((name, next_name, "fall-through".into()));
+            }
+        }
+        let mut seen = HashSet::new();
+        for edge in edges.iter().filter(|&edge| seen.insert(edge)) {
+            let (from, to, kind) = edge;
+            lines.push(format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];",
+                from, to, kind
+            ));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    /// Checks that every operation applied by the installed controller is available,
+    /// and fails with all the unknown operation names otherwise.
+    /// Unlike `validate`, only the operations are checked.
+    pub fn check_operations(&self) -> MResult<()> {
+        let mut unknown: Vec<String> = vec![];
+        for (name, _) in self.the_inst_seq.iter().flat_map(applied_operations) {
+            if !self.has_operation(name) && !unknown.iter().any(|n| n == name) {
+                unknown.push(name.to_string());
+            }
+        }
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            warn!("unknown operations: {:?}", unknown);
+            Err(ProcedureError::UnknownOperations(unknown))?
+        }
     }
 
-    pub fn install_labels(&mut self, labels: HashMap<String, Vec<RMLNode>>) {
+    /// Checks the installed controller before running it, and returns all the problems found:
+    /// unknown labels, unknown operations, too few operation arguments,
+    /// registers read before written, and unbalanced stack operations.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        let mut report = |severity, index, message| {
+            diagnostics.push(Diagnostic {
+                severity,
+                index,
+                message,
+            })
+        };
+        // The registers set by the host before running are written already.
+        let unassigned = Value::Symbol("*unassigned*".into());
+        let initial = self
+            .register_table
+            .iter()
+            .filter(|(_, reg)| reg.get() != unassigned)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let mut unwritten_reads =
+            reads_before_writes(&self.the_inst_seq, &self.label_indices(), initial)
+                .into_iter()
+                .peekable();
+        let (mut saves, mut restores) = (0, 0);
+        for (i, inst) in self.the_inst_seq.iter().enumerate() {
+            let index = Some(i);
+            for label in referenced_labels(inst) {
+                if !self.the_labels.contains_key(label) {
+                    report(Severity::Error, index, format!("Unknown label: {}", label));
+                }
+            }
+            for (name, arg_num) in applied_operations(inst) {
+                if !self.has_operation(name) {
+                    report(
+                        Severity::Error,
+                        index,
+                        format!("Unknown operation: {}", name),
+                    );
+                } else if let Some(proc) = self.the_procedures.get(name) {
+                    if arg_num < proc.get_arg_num() {
+                        report(
+                            Severity::Error,
+                            index,
+                            format!(
+                                "Operation {} expected {} arguments, got {}",
+                                name,
+                                proc.get_arg_num(),
+                                arg_num
+                            ),
+                        );
+                    } else if let Some(max) = proc.get_max_arg_num().filter(|&max| arg_num > max) {
+                        report(
+                            Severity::Error,
+                            index,
+                            format!(
+                                "Operation {} expected at most {} arguments, got {}",
+                                name, max, arg_num
+                            ),
+                        );
+                    }
+                }
+            }
+            while let Some((_, reg_name)) = unwritten_reads.next_if(|(at, _)| *at == i) {
+                report(
+                    Severity::Warning,
+                    index,
+                    format!("Register {} may be read before written", reg_name),
+                );
+            }
+            match inst {
+                RMLNode::Save(_) => saves += 1,
+                RMLNode::Restore(_) => restores += 1,
+                _ => (),
+            }
+        }
+        if saves != restores {
+            report(
+                Severity::Warning,
+                None,
+                format!("Unbalanced stack: {} saves, {} restores", saves, restores),
+            );
+        }
+        diagnostics
+    }
+
+    /// Installs the labels, mapped to the indices of their first instructions.
+    pub fn install_labels(&mut self, labels: HashMap<String, usize>) {
         self.the_labels = labels;
     }
 
     pub fn start(&mut self) -> MResult<&'static str> {
+        self.run(None)
+    }
+
+    /// Starts the machine like `start`, but fails with `StepLimitExceeded`
+    /// once `max_steps` instructions have been executed.
+    /// The registers are left as they were when the limit was hit.
+    pub fn run_with_limit(&mut self, max_steps: usize) -> MResult<&'static str> {
+        self.run(Some(max_steps))
+    }
+
+    fn run(&mut self, max_steps: Option<usize>) -> MResult<&'static str> {
         trace!("start");
         info!("machine starting");
+        self.inst_counts.iter_mut().for_each(|count| *count = 0);
+        self.inst_stats = InstructionStats::default();
+        self.jump_count = 0;
+        self.jump_target_counts.clear();
+        self.operation_counts.clear();
+        self.stack.reset_statistics();
+        self.register_stacks
+            .values_mut()
+            .for_each(Stack::reset_statistics);
+        self.register_histories.values_mut().for_each(Vec::clear);
+        self.tail_call_depths.clear();
+        self.paused_pc = None;
+        self.history.clear();
         self.reset_pc();
-        self.execute()
+        let start = Instant::now();
+        self.run_start = Some(start);
+        let res = self.execute_steps(max_steps, false);
+        self.run_duration = start.elapsed();
+        res.map(|_| "Done")
+    }
+
+    /// Estimates the recursion depth of the last run from the maximum stack depth,
+    /// for a controller saving `saves_per_call` registers in each recursive call.
+    /// `None` if `saves_per_call` is zero.
+    pub fn estimated_recursion_depth(&self, saves_per_call: usize) -> Option<i32> {
+        let (_, maximum_depth) = self.stack_statistics();
+        match saves_per_call {
+            0 => None,
+            saves_per_call => Some(maximum_depth / saves_per_call as i32),
+        }
+    }
+
+    /// Starts the machine with the procedures of `io::install_io_procedures`
+    /// reading from `input`, and returns the text they write.
+    /// The original procedures are restored afterwards.
+    pub fn run_with_input(&mut self, input: &str) -> MResult<String> {
+        let input = Arc::new(Mutex::new(Cursor::new(input.to_string())));
+        let output = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let saved: Vec<Procedure> = io::IO_PROCEDURES
+            .iter()
+            .filter_map(|&name| self.the_procedures.get(name).cloned())
+            .collect();
+        io::install_io_procedures(self, input, output.clone());
+        let res = self.start();
+        for name in io::IO_PROCEDURES.iter() {
+            self.the_procedures.remove(*name);
+        }
+        self.install_procedures(&saved);
+        res?;
+        let output = output.lock().unwrap().clone();
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Returns how many times each operation was called during the last run,
+    /// the most called operations first.
+    pub fn operation_call_counts(&self) -> Vec<(String, u64)> {
+        let mut counts: Vec<(String, u64)> = self
+            .operation_counts
+            .iter()
+            .map(|(name, &count)| (name.to_string(), count))
+            .collect();
+        counts.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then_with(|| a_name.cmp(b_name)));
+        counts
+    }
+
+    /// Returns how many times each label was jumped to during the last run,
+    /// the most jumped-to labels first.
+    pub fn jump_targets(&self) -> Vec<(String, u64)> {
+        let mut targets: Vec<(String, u64)> = self
+            .jump_target_counts
+            .iter()
+            .map(|(name, &count)| (name.to_string(), count))
+            .collect();
+        targets.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then_with(|| a_name.cmp(b_name)));
+        targets
+    }
+
+    pub fn instruction_stats(&self) -> InstructionStats {
+        self.inst_stats.clone()
+    }
+
+    pub fn run_stats(&self) -> RunStats {
+        let (total_pushes, maximum_depth) = self.stack_statistics();
+        RunStats {
+            instructions: self.inst_counts.iter().sum(),
+            total_pushes,
+            maximum_depth,
+            jumps: self.jump_count,
+            duration: self.run_duration,
+        }
     }
 
     pub fn execute(&mut self) -> MResult<&'static str> {
+        self.execute_steps(None, false).map(|_| "Done")
+    }
+
+    /// Executes from the current pc like `execute`, but pauses when the pc reaches
+    /// a label set by `set_breakpoint`, whether by a jump or by falling through.
+    /// Calling it again resumes from the paused label.
+    pub fn proceed(&mut self) -> MResult<RunState> {
+        self.execute_steps(None, true)
+    }
+
+    /// Makes `proceed` pause before executing the first instruction of `label`.
+    pub fn set_breakpoint(&mut self, label: &str) {
+        self.breakpoints.insert(label.to_string());
+    }
+
+    pub fn clear_breakpoint(&mut self, label: &str) {
+        self.breakpoints.remove(label);
+    }
+
+    /// The breakpoint label starting at the pc, the first one by name if several labels do.
+    fn breakpoint_at_pc(&self) -> Option<String> {
+        match self.pc.get() {
+            Value::Pointer(pc) => self
+                .breakpoints
+                .iter()
+                .filter(|label| self.the_labels.get(label.as_str()) == Some(&pc))
+                .min()
+                .cloned(),
+            _ => None,
+        }
+    }
+
+    fn execute_steps(&mut self, max_steps: Option<usize>, breakpoints: bool) -> MResult<RunState> {
         trace!("execute instructions");
-        loop {
-            if let Value::Pointer(pointer) = self.pc.get() {
-                debug!("current pc: {}", pointer);
-                if pointer == self.the_inst_seq.len() {
-                    info!("finished");
-                    return Ok("Done");
-                } else if pointer > self.the_inst_seq.len() {
-                    warn!("no more instructions");
-                    return Err(MachineError::NoMoreInsts);
+        let mut steps = 0;
+        // The pc paused at last time, which is resumed from instead of pausing again.
+        let resumed_pc = self.paused_pc.take();
+        let res = loop {
+            if max_steps.is_some_and(|max_steps| steps >= max_steps) {
+                warn!("step limit exceeded");
+                break Err(MachineError::StepLimitExceeded(steps));
+            }
+            let pc = match self.pc.get() {
+                Value::Pointer(pc) => Some(pc),
+                _ => None,
+            };
+            if breakpoints && !(steps == 0 && resumed_pc.is_some() && resumed_pc == pc) {
+                if let Some(label) = self.breakpoint_at_pc() {
+                    info!("paused at {}", label);
+                    self.paused_pc = pc;
+                    break Ok(RunState::Paused { label });
                 }
-                debug!("current inst: {}", &self.the_inst_seq[pointer]);
-                match self.the_inst_seq[pointer].clone() {
-                    RMLNode::Assignment(reg_name, op) => self.execute_assignment(reg_name, op)?,
-                    RMLNode::Branch(label) => self.execute_branch(label)?,
-                    RMLNode::GotoLabel(label) => self.execute_goto(label)?,
-                    RMLNode::PerformOp(op) => self.execute_perform(op)?,
-                    RMLNode::Restore(reg_name) => self.execute_restore(reg_name)?,
-                    RMLNode::Save(reg_name) => self.execute_save(reg_name)?,
-                    RMLNode::TestOp(op) => self.execute_test(op)?,
-                    _ => unreachable!(),
-                };
-            } else {
-                warn!("unexpected type: {:?}", self.pc.get());
-                return Err(RegisterError::UnmatchedContentType {
-                    reg_name: "pc".to_string(),
-                    type_name: "usize".to_string(),
-                })?;
             }
+            match self.step() {
+                Ok(true) => steps += 1,
+                Ok(false) => break Ok(RunState::Done),
+                Err(e) => break Err(e),
+            }
+        };
+        if let Some(writer) = self.trace_writer.as_mut() {
+            writer
+                .flush()
+                .map_err(|e| MachineError::TraceFailure(e.to_string()))?;
+        }
+        if res == Ok(RunState::Done) {
+            info!("finished");
+        }
+        res
+    }
+
+    /// Writes each executed instruction to `writer` as the machine runs,
+    /// one line per instruction with its index and the label of its block,
+    /// e.g. `0004 rem-loop: (test (op <) (reg t) (reg b))`.
+    /// The writer is flushed periodically, and when the execution stops.
+    pub fn trace_to(&mut self, writer: Box<dyn Write + Send>) {
+        let mut labels: Vec<(usize, String)> = self
+            .the_labels
+            .iter()
+            .map(|(name, &index)| (index, name.to_string()))
+            .collect();
+        labels.sort_unstable();
+        self.trace_labels = labels;
+        self.traced_lines = 0;
+        self.trace_writer = Some(writer);
+    }
+
+    /// Calls `hook` with the new pc whenever the pc changes,
+    /// when advancing, jumping, resetting or restoring a continuation.
+    pub fn set_pc_hook(&mut self, hook: Box<dyn FnMut(usize) + Send>) {
+        self.pc_hook = Some(hook);
+    }
+
+    /// Calls `hook` before executing each instruction,
+    /// with the instruction and a view of the registers and the stack.
+    pub fn set_trace_hook(&mut self, hook: TraceHook) {
+        self.trace_hook = Some(hook);
+    }
+
+    /// Traces the writes to the register, like `trace-on-register` in SICP exercise 5.18.
+    /// Each write is logged with the old and the new contents,
+    /// and passed to the hook set by `set_register_trace_hook`.
+    pub fn trace_register(&mut self, reg_name: &str) {
+        self.traced_registers.insert(reg_name.to_string());
+    }
+
+    pub fn untrace_register(&mut self, reg_name: &str) {
+        self.traced_registers.remove(reg_name);
+    }
+
+    pub fn set_register_trace_hook(&mut self, hook: RegisterTraceHook) {
+        self.register_trace_hook = Some(hook);
+    }
+
+    /// Records every value written to the register, to be read by `register_history`.
+    /// The history is cleared when the machine starts.
+    pub fn track_register(&mut self, reg_name: &str) {
+        self.register_histories
+            .entry(reg_name.to_string())
+            .or_default();
+    }
+
+    /// Returns the values written to the register since the machine started,
+    /// or `None` if the register isn't tracked by `track_register`.
+    pub fn register_history(&self, reg_name: &str) -> Option<Vec<Value>> {
+        self.register_histories.get(reg_name).cloned()
+    }
+
+    fn call_trace_hook(&mut self, index: usize) {
+        // Taken out while called, so the snapshot can borrow the machine.
+        if let Some(mut hook) = self.trace_hook.take() {
+            hook(
+                &self.the_inst_seq[index],
+                &MachineSnapshot { machine: self },
+            );
+            self.trace_hook = Some(hook);
+        }
+    }
+
+    fn write_trace(&mut self, index: usize) -> MResult<()> {
+        let label = match self
+            .trace_labels
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= index)
+        {
+            Some((_, name)) => format!(" {}:", name),
+            None => String::new(),
+        };
+        self.traced_lines += 1;
+        let flush = self.traced_lines.is_multiple_of(TRACE_FLUSH_INTERVAL);
+        if let Some(writer) = self.trace_writer.as_mut() {
+            let inst = &self.the_inst_seq[index];
+            writeln!(writer, "{:04}{} {}", index, label, inst)
+                .and_then(|_| if flush { writer.flush() } else { Ok(()) })
+                .map_err(|e| MachineError::TraceFailure(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Records the state before each executed instruction, keeping the latest `depth` ones
+    /// to be restored by `step_back`. A zero `depth` disables the history.
+    /// The history is cleared when the machine starts or resets.
+    pub fn enable_history(&mut self, depth: usize) {
+        self.history_depth = depth;
+        while self.history.len() > depth {
+            self.history.pop_front();
+        }
+    }
+
+    /// Restores the state before the latest executed instruction recorded in the history.
+    /// The statistics of the run aren't restored.
+    /// Returns `false` if the history is empty.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.pop_back() {
+            Some(continuation) => {
+                self.restore_continuation(&continuation);
+                true
+            }
+            None => false,
         }
     }
 
+    /// Executes the instruction that the pc register points to.
+    /// Returns `false` if there are no instructions left to be executed.
+    pub fn step(&mut self) -> MResult<bool> {
+        if let Value::Pointer(pointer) = self.pc.get() {
+            debug!("current pc: {}", pointer);
+            if pointer == self.the_inst_seq.len() {
+                return Ok(false);
+            } else if pointer > self.the_inst_seq.len() {
+                warn!("no more instructions");
+                return Err(MachineError::NoMoreInsts);
+            }
+            debug!("current inst: {}", &self.the_inst_seq[pointer]);
+            if self.history_depth > 0 {
+                if self.history.len() == self.history_depth {
+                    self.history.pop_front();
+                }
+                self.history.push_back(self.save_continuation());
+            }
+            self.inst_counts[pointer] += 1;
+            self.inst_stats.count(&self.the_inst_seq[pointer]);
+            if self.trace_writer.is_some() {
+                self.write_trace(pointer)?;
+            }
+            if self.trace_hook.is_some() {
+                self.call_trace_hook(pointer);
+            }
+            match self.the_inst_seq[pointer].clone() {
+                RMLNode::Assignment(reg_name, op) => self.execute_assignment(reg_name, op)?,
+                RMLNode::Branch(label) => self.execute_branch(label)?,
+                RMLNode::Dispatch(reg) => self.execute_dispatch(reg)?,
+                RMLNode::GotoIfEq(lhs, rhs, label) => self.execute_goto_if_eq(lhs, rhs, label)?,
+                RMLNode::GotoLabel(label) => self.execute_goto(label)?,
+                RMLNode::IfFlag(consequent, alternative) => {
+                    self.execute_if_flag(consequent, alternative)?
+                }
+                RMLNode::PerformOp(op) => self.execute_perform(op)?,
+                RMLNode::Restore(reg_name) => self.execute_restore(reg_name)?,
+                RMLNode::Save(reg_name) => self.execute_save(reg_name)?,
+                RMLNode::TailCall(label) => self.execute_tailcall(label)?,
+                RMLNode::TestOp(op) => self.execute_test(op)?,
+                _ => unreachable!(),
+            };
+            Ok(true)
+        } else {
+            warn!("unexpected type: {:?}", self.pc.get());
+            Err(RegisterError::UnmatchedContentType {
+                reg_name: "pc".to_string(),
+                type_name: "usize".to_string(),
+            })?
+        }
+    }
+
+    /// Clears the contents of all registers, except `pc` and `flag`.
+    pub fn reset_registers(&mut self) {
+        self.reset_registers_except(&[]);
+    }
+
+    /// Clears the contents of all registers like `reset_registers`,
+    /// except the ones named in `kept`, e.g. to preserve the inputs.
+    pub fn reset_registers_except(&mut self, kept: &[&str]) {
+        trace!("reset registers except {:?}", kept);
+        self.register_table
+            .iter_mut()
+            .filter(|(name, _)| !kept.contains(&name.as_str()))
+            .for_each(|(_, reg)| *reg = Register::new());
+    }
+
+    /// Clears the stack and its statistics.
+    pub fn reset_stack(&mut self) {
+        trace!("reset stack");
+        self.initialize_stack();
+    }
+
+    pub fn reset_flag(&mut self) {
+        trace!("reset flag");
+        self.flag = Register::new();
+    }
+
+    /// Clears the registers, the stack and the flag, and moves the pc back to the start,
+    /// keeping the installed instructions, labels and procedures to run again.
+    /// The history of `enable_history` is cleared as well.
+    pub fn reset(&mut self) {
+        self.reset_registers();
+        self.reset_stack();
+        self.reset_flag();
+        self.reset_pc();
+        self.history.clear();
+    }
+
+    /// Captures the current execution state.
+    pub fn save_continuation(&self) -> Continuation {
+        Continuation {
+            state: Arc::new(ContinuationState {
+                pc: self.pc.clone(),
+                flag: self.flag.clone(),
+                stack: self.stack.clone(),
+                register_stacks: self.register_stacks.clone(),
+                register_table: self.register_table.clone(),
+            }),
+        }
+    }
+
+    /// Resumes the execution state captured by `save_continuation`,
+    /// `execute` or `step` continues from there.
+    pub fn restore_continuation(&mut self, continuation: &Continuation) {
+        let state = &*continuation.state;
+        self.pc = state.pc.clone();
+        if let Value::Pointer(index) = self.pc.get() {
+            self.notify_pc(index);
+        }
+        self.flag = state.flag.clone();
+        self.stack = state.stack.clone();
+        self.register_stacks = state.register_stacks.clone();
+        self.register_table = state.register_table.clone();
+    }
+
+    /// Captures the current execution state as plain values.
+    pub fn snapshot(&self) -> MachineState {
+        MachineState {
+            pc: match self.pc.get() {
+                Value::Pointer(pc) => pc,
+                _ => unreachable!(),
+            },
+            flag: self.flag.get(),
+            stack: self.stack.items().to_vec(),
+            register_stacks: self
+                .register_stacks
+                .iter()
+                .map(|(name, stack)| (name.clone(), stack.items().to_vec()))
+                .collect(),
+            registers: self
+                .register_table
+                .iter()
+                .map(|(name, reg)| (name.clone(), reg.get()))
+                .collect(),
+        }
+    }
+
+    /// Resumes the execution state captured by `snapshot`, restarting the stack statistics.
+    /// The procedure values are replaced with the installed procedures of the same names,
+    /// so a state converted from JSON calls the procedures of this machine.
+    /// Fails without changing anything if the pc is past the end of the controller,
+    /// a register of the state isn't allocated, a procedure isn't installed,
+    /// or the stack contents exceed the stack limit.
+    pub fn restore(&mut self, state: &MachineState) -> MResult<()> {
+        if state.pc > self.the_inst_seq.len() {
+            return Err(MachineError::PcOutOfRange {
+                pc: state.pc,
+                len: self.the_inst_seq.len(),
+            });
+        }
+        if let Some(name) = state
+            .registers
+            .keys()
+            .find(|name| !self.register_table.contains_key(name.as_str()))
+        {
+            return Err(RegisterError::LookupFailure(name.clone()))?;
+        }
+        let procedures = &self.the_procedures;
+        let registers = state
+            .registers
+            .iter()
+            .map(|(name, value)| Ok((name, resolve_procedures(procedures, value)?)))
+            .collect::<MResult<Vec<(&String, Value)>>>()?;
+        let flag = resolve_procedures(procedures, &state.flag)?;
+        let limit = self.stack.max_depth_limit();
+        let refill = |items: &[Value]| -> MResult<Stack> {
+            let mut stack = Stack::new();
+            stack.set_max_depth_limit(limit);
+            for item in items.iter() {
+                let depth = stack.current_depth() as usize;
+                stack
+                    .push(resolve_procedures(procedures, item)?)
+                    .map_err(|_| MachineError::StackOverflow { depth })?;
+            }
+            Ok(stack)
+        };
+        let stack = refill(&state.stack)?;
+        let register_stacks = state
+            .register_stacks
+            .iter()
+            .map(|(name, items)| Ok((name.clone(), refill(items)?)))
+            .collect::<MResult<HashMap<String, Stack>>>()?;
+        self.stack = stack;
+        self.register_stacks = register_stacks;
+        self.flag.set(flag);
+        for (name, value) in registers.into_iter() {
+            if let Some(reg) = self.register_table.get_mut(name) {
+                reg.set(value);
+            }
+        }
+        self.set_pc(state.pc);
+        Ok(())
+    }
+
+    /// Returns the contents of the flag register.
+    pub fn flag(&self) -> Value {
+        self.flag.get()
+    }
+
+    /// Sets the flag register, which decides the next `branch` or `if-flag`.
+    pub fn set_flag(&mut self, value: bool) {
+        debug!("set flag: {}", value);
+        self.flag.set(value);
+    }
+
+    /// Installs `+`, `-` and `*` operations handling an overflow of two integers by `mode`,
+    /// replacing the installed ones. `None` installs the standard ones,
+    /// promoting the result to a float point number.
+    pub fn set_integer_overflow_mode(&mut self, mode: Option<IntegerOverflowMode>) {
+        self.install_procedures(&math::arithmetic_procedures(mode));
+    }
+
+    /// Installs an `=` operation treating numbers as equal if they differ
+    /// by at most `epsilon`, replacing the installed one. `0.0` installs the standard one.
+    pub fn set_float_epsilon(&mut self, epsilon: f64) {
+        self.install_procedure(math::equal_procedure(epsilon));
+    }
+
+    /// Makes executing the last instruction of the controller and advancing past it
+    /// fail with `FellOffEnd`, so the controller must finish by going to a label
+    /// at the end, like `done`. Disabled by default.
+    pub fn set_require_explicit_halt(&mut self, enabled: bool) {
+        self.require_explicit_halt = enabled;
+    }
+
+    /// Enables or disables the stack depth check of `tailcall`, enabled by default.
+    pub fn set_tail_call_check(&mut self, enabled: bool) {
+        self.check_tail_calls = enabled;
+    }
+
     fn advance_pc(&mut self) -> MResult<&'static str> {
         trace!("increment the pc register");
         if let Value::Pointer(p) = self.pc.get() {
-            self.pc.set(Value::Pointer(p + 1));
+            self.set_pc(p + 1);
             debug!("new pc: {}", self.pc.get());
+            if self.require_explicit_halt && p + 1 == self.the_inst_seq.len() {
+                warn!("fell off the end of the controller");
+                return Err(MachineError::FellOffEnd);
+            }
             Ok("Done")
         } else {
             warn!("unexpected type: {:?}", self.pc.get());
@@ -207,7 +1402,18 @@ impl Machine {
     fn reset_pc(&mut self) {
         trace!("reset the pc register");
         debug!("reset pc: {} to 0", self.pc.get());
-        self.pc.set(Value::Pointer(0));
+        self.set_pc(0);
+    }
+
+    fn set_pc(&mut self, index: usize) {
+        self.pc.set(Value::Pointer(index));
+        self.notify_pc(index);
+    }
+
+    fn notify_pc(&mut self, index: usize) {
+        if let Some(hook) = self.pc_hook.as_mut() {
+            hook(index);
+        }
     }
 
     fn execute_assignment(
@@ -267,7 +1473,7 @@ impl Machine {
                     })?
                 }
             }
-            RMLNode::Label(label_name) => {
+            RMLNode::Label(label_name) | RMLNode::Constant(RMLValue::Symbol(label_name)) => {
                 debug!("label: {}", label_name);
                 Ok(label_name.to_string())
             }
@@ -275,32 +1481,118 @@ impl Machine {
         }
     }
 
-    fn execute_branch(&mut self, label: Arc<RMLNode>) -> MResult<&'static str> {
-        trace!("branch");
+    fn execute_branch(&mut self, label: Arc<RMLNode>) -> MResult<&'static str> {
+        trace!("branch");
+        let label_name = self.extract_label_name(label)?;
+        if !self.the_labels.contains_key(&label_name) {
+            warn!("unknown label: {}", &label_name);
+            Err(MachineError::UnknownLabel(label_name))
+        } else if let Value::Boolean(true) = self.flag.get() {
+            debug!("jump to {}", &label_name);
+            self.jump_to(label_name)
+        } else {
+            debug!("don't jump, go on");
+            self.advance_pc()
+        }
+    }
+
+    fn execute_dispatch(&mut self, reg: Arc<RMLNode>) -> MResult<&'static str> {
+        trace!("dispatch");
+        let op_name = match self.operand_value(&reg)? {
+            Value::Symbol(name) => name,
+            other => {
+                warn!("unexpected type: {}", other);
+                return Err(TypeError::expected("Value::Symbol").got(other.to_string()))?;
+            }
+        };
+        let args = match self.get_register_content("argl")? {
+            Value::List(args) => args,
+            other => {
+                warn!("unexpected type: {}", other);
+                return Err(TypeError::expected("Value::List").got(other.to_string()))?;
+            }
+        };
+        debug!("dispatch to op: {}", op_name);
+        let value = self.call_procedure(op_name, args)?;
+        self.set_register_content("val", value)?;
+        self.advance_pc()
+    }
+
+    fn execute_goto(&mut self, label: Arc<RMLNode>) -> MResult<&'static str> {
+        trace!("goto");
         let label_name = self.extract_label_name(label)?;
-        if let Some(insts) = self.the_labels.get(&label_name) {
-            if let Value::Boolean(true) = self.flag.get() {
-                debug!("jump to {}", &label_name);
-                self.the_inst_seq = insts.clone();
-                self.reset_pc();
-                Ok("Done")
-            } else {
-                debug!("don't jump, go on");
-                self.advance_pc()
-            }
+        self.jump_to(label_name)
+    }
+
+    fn execute_goto_if_eq(
+        &mut self,
+        lhs: Arc<RMLNode>,
+        rhs: Arc<RMLNode>,
+        label: Arc<RMLNode>,
+    ) -> MResult<&'static str> {
+        trace!("goto-if-eq");
+        let lhs = self.operand_value(&lhs)?;
+        let rhs = self.operand_value(&rhs)?;
+        if lhs == rhs {
+            debug!("{} equals to {}, jump", lhs, rhs);
+            let label_name = self.extract_label_name(label)?;
+            self.jump_to(label_name)
         } else {
-            warn!("unknown label: {}", &label_name);
-            Err(MachineError::UnknownLabel(label_name))
+            debug!("{} doesn't equal to {}, go on", lhs, rhs);
+            self.advance_pc()
         }
     }
 
-    fn execute_goto(&mut self, label: Arc<RMLNode>) -> MResult<&'static str> {
-        trace!("goto");
+    fn execute_if_flag(
+        &mut self,
+        consequent: Arc<RMLNode>,
+        alternative: Arc<RMLNode>,
+    ) -> MResult<&'static str> {
+        trace!("if-flag");
+        let label = if let Value::Boolean(true) = self.flag.get() {
+            consequent
+        } else {
+            alternative
+        };
+        let label_name = self.extract_label_name(label)?;
+        self.jump_to(label_name)
+    }
+
+    /// Jumps like `goto`, but fails if the stack depth differs from the one
+    /// at the previous tail call of the label, so the call isn't a tail call.
+    fn execute_tailcall(&mut self, label: Arc<RMLNode>) -> MResult<&'static str> {
+        trace!("tailcall");
         let label_name = self.extract_label_name(label)?;
-        if let Some(insts) = self.the_labels.get(&label_name) {
-            debug!("go to label: {}", &label_name);
-            self.the_inst_seq = insts.clone();
-            self.reset_pc();
+        let depth = self.stack_depth();
+        if self.check_tail_calls {
+            let expected = *self
+                .tail_call_depths
+                .entry(label_name.clone())
+                .or_insert(depth);
+            if expected != depth {
+                warn!("not a tail call to {}", &label_name);
+                return Err(MachineError::NotTailCall {
+                    label: label_name,
+                    expected,
+                    got: depth,
+                });
+            }
+        }
+        self.enter_label(label_name)
+    }
+
+    fn jump_to(&mut self, label_name: String) -> MResult<&'static str> {
+        // Entering the label other than by a tail call starts a new call.
+        self.tail_call_depths.remove(&label_name);
+        self.enter_label(label_name)
+    }
+
+    fn enter_label(&mut self, label_name: String) -> MResult<&'static str> {
+        if let Some(&index) = self.the_labels.get(&label_name) {
+            debug!("go to label: {} at {}", &label_name, index);
+            self.jump_count += 1;
+            *self.jump_target_counts.entry(label_name).or_insert(0) += 1;
+            self.set_pc(index);
             Ok("Done")
         } else {
             warn!("unknown label: {}", &label_name);
@@ -324,10 +1616,18 @@ impl Machine {
 
     fn execute_restore(&mut self, reg_name: String) -> MResult<&'static str> {
         trace!("restore");
-        let value = self
-            .stack
-            .pop()
-            .map_err(|s: &str| MachineError::StackError(s.to_string()))?;
+        let value = if self.per_register_stacks {
+            self.register_stacks
+                .get_mut(&reg_name)
+                .and_then(|stack| stack.pop().ok())
+                .ok_or_else(|| {
+                    MachineError::StackError(format!("no saved contents of {}", reg_name))
+                })?
+        } else {
+            self.stack
+                .pop()
+                .map_err(|s: &str| MachineError::StackError(s.to_string()))?
+        };
         debug!("reg: {} restore to val: {}", reg_name, value);
         self.set_register_content(&reg_name, value)?;
         self.advance_pc()
@@ -337,7 +1637,23 @@ impl Machine {
         trace!("save");
         let value = self.get_register_content(&reg_name)?;
         debug!("reg: {}, value: {}, saved", reg_name, value);
-        self.stack.push(value);
+        let stack = if self.per_register_stacks {
+            let limit = self.stack.max_depth_limit();
+            self.register_stacks
+                .entry(reg_name.clone())
+                .or_insert_with(|| {
+                    let mut stack = Stack::new();
+                    stack.set_max_depth_limit(limit);
+                    stack
+                })
+        } else {
+            &mut self.stack
+        };
+        let depth = stack.current_depth() as usize;
+        stack.push(value).map_err(|e| {
+            warn!("{}: {}", e, reg_name);
+            MachineError::StackOverflow { depth }
+        })?;
         self.advance_pc()
     }
 
@@ -364,20 +1680,13 @@ impl Machine {
     fn perform_operation<S: Into<String>>(
         &mut self,
         op_name: S,
-        args: &Vec<RMLNode>,
+        args: &[RMLNode],
     ) -> MResult<Value> {
         trace!("perform an operation");
         let op_name = op_name.into();
         let mut op_args: Vec<Value> = vec![];
         for arg in args.iter() {
-            match arg {
-                RMLNode::Reg(r) => {
-                    let value = self.get_register_content(r)?;
-                    op_args.push(value);
-                }
-                RMLNode::Constant(value) => op_args.push(rmlvalue_to_value(value)),
-                _ => unreachable!(),
-            }
+            op_args.push(self.operand_value(arg)?);
         }
         debug!(
             "op: {} performs with args: ({})",
@@ -386,19 +1695,181 @@ impl Machine {
         );
         self.call_procedure(op_name, op_args)
     }
+
+    fn operand_value(&mut self, operand: &RMLNode) -> MResult<Value> {
+        match operand {
+            RMLNode::Operation(op_name, args) => self.perform_operation(op_name, args),
+            RMLNode::Reg(r) => self.get_register_content(r),
+            RMLNode::Constant(value) => Ok(rmlvalue_to_value(value)),
+            RMLNode::Label(name) => self
+                .the_labels
+                .get(name)
+                .map(|&index| Value::Pointer(index))
+                .ok_or_else(|| MachineError::UnknownLabel(name.to_string())),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Executes the procedure on a worker thread, waiting for at most `timeout`.
+/// A panic of the procedure is resumed on the calling thread.
+fn execute_with_timeout(op: Procedure, args: Vec<Value>, timeout: Duration) -> MResult<Value> {
+    let name = op.get_name();
+    let (sender, receiver) = mpsc::channel();
+    let worker = thread::spawn(move || sender.send(op.execute(args)));
+    match receiver.recv_timeout(timeout) {
+        Ok(res) => res,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            warn!("operation timeout: {}", name);
+            Err(MachineError::OperationTimeout(name))
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => match worker.join() {
+            Err(panic) => std::panic::resume_unwind(panic),
+            Ok(_) => unreachable!(),
+        },
+    }
 }
 
 #[cfg(test)]
-mod machine_tests {
+pub(crate) mod machine_tests {
     use super::*;
-    use crate::make_proc;
+    use crate::{
+        assemble::extract_labels, machine::value::TryFromValue, make_machine,
+        make_machine_with_standard_ops, make_proc, math,
+    };
+
+    const FIBONACCI: &str = r#"
+    (controller
+       (assign continue (label fib-done))
+     fib-loop
+       (test (op <) (reg n) (const 2))
+       (branch (label immediate-answer))
+       (save continue)
+       (assign continue (label afterfib-n-1))
+       (save n)
+       (assign n (op -) (reg n) (const 1))
+       (goto (label fib-loop))
+     afterfib-n-1
+       (restore n)
+       (restore continue)
+       (assign n (op -) (reg n) (const 2))
+       (save continue)
+       (assign continue (label afterfib-n-2))
+       (save val)
+       (goto (label fib-loop))
+     afterfib-n-2
+       (assign n (reg val))
+       (restore val)
+       (restore continue)
+       (assign val (op +) (reg val) (reg n))
+       (goto (reg continue))
+     immediate-answer
+       (assign val (reg n))
+       (goto (reg continue))
+     fib-done)
+    "#;
+
+    pub(crate) const GCD: &str = r#"
+    (controller
+     test-b
+       (test (op =) (reg b) (const 0))
+       (branch (label gcd-done))
+       (assign t (op rem) (reg a) (reg b))
+       (assign a (reg b))
+       (assign b (reg t))
+       (goto (label test-b))
+     gcd-done)
+    "#;
+
+    /// The machine of `GCD`, computing the GCD of `a` and `b` into `a`.
+    pub(crate) fn gcd_machine(a: i32, b: i32) -> Machine {
+        let procedures = vec![
+            Procedure::new("=", 2, math::equal),
+            Procedure::fallible("rem", 2, math::remainder),
+        ];
+        let mut m = make_machine(vec!["a", "b", "t"], &procedures, GCD).unwrap();
+        m.set_register_content("a", a).unwrap();
+        m.set_register_content("b", b).unwrap();
+        m
+    }
+
+    /// The Fibonacci machine reading each `n` and printing the result, until `q` is read.
+    pub(crate) const FIBONACCI_LOOP: &str = r#"
+    (controller
+       (perform (op print) (const "n: "))
+       (assign n (op read))
+       (test (op =) (reg n) (const q))
+       (branch (label done))
+       (assign continue (label fib-done))
+     fib-loop
+       (test (op <) (reg n) (const 2))
+       (branch (label immediate-answer))
+       (save continue)
+       (assign continue (label afterfib-n-1))
+       (save n)
+       (assign n (op -) (reg n) (const 1))
+       (goto (label fib-loop))
+     afterfib-n-1
+       (restore n)
+       (restore continue)
+       (assign n (op -) (reg n) (const 2))
+       (save continue)
+       (assign continue (label afterfib-n-2))
+       (save val)
+       (goto (label fib-loop))
+     afterfib-n-2
+       (assign n (reg val))
+       (restore val)
+       (restore continue)
+       (assign val (op +) (reg val) (reg n))
+       (goto (reg continue))
+     immediate-answer
+       (assign val (reg n))
+       (goto (reg continue))
+     fib-done
+       (perform (op print) (reg val))
+       (goto (label controller))
+     done)
+    "#;
+
+    /// The operations of `FIBONACCI` and `FIBONACCI_LOOP`.
+    pub(crate) fn fibonacci_procedures() -> Vec<Procedure> {
+        vec![
+            Procedure::new("=", 2, math::equal),
+            Procedure::new("<", 2, math::less_than),
+            Procedure::new("+", 2, math::addition),
+            Procedure::new("-", 2, math::subtraction),
+        ]
+    }
+
+    fn fibonacci_machine(n: i32) -> Machine {
+        let procedures = fibonacci_procedures();
+        let mut m = make_machine(vec!["continue", "n", "val"], &procedures, FIBONACCI).unwrap();
+        m.set_register_content("n", n).unwrap();
+        m
+    }
 
     #[test]
     fn test_make_new_machine() {
         let m = Machine::new();
         assert!(m.stack.is_empty());
         assert_eq!(m.total_registers(), 2);
-        assert_eq!(m.total_procedures(), 2);
+        assert_eq!(m.total_procedures(), 4);
+    }
+
+    #[test]
+    fn test_make_machine_with_capacity() {
+        let mut m = Machine::with_capacity(8, 64);
+        assert_eq!(m.total_registers(), 2);
+        assert_eq!(m.total_procedures(), 4);
+        for i in 0..64 {
+            m.install_procedure(Procedure::new(format!("add-{}", i), 1, move |args| {
+                args[0].clone() + Value::new(i)
+            }));
+        }
+        assert_eq!(m.total_procedures(), 68);
+        let res = m.call_procedure("add-10", vec![Value::new(1)]);
+        assert_eq!(Ok(Value::new(11)), res);
     }
 
     #[test]
@@ -407,12 +1878,19 @@ mod machine_tests {
         let res = m.allocate_register("test");
         assert_eq!(res, Ok("register-allocated"));
 
-        match m.allocate_register("test") {
-            Err(e) => assert_eq!(
-                MachineError::RegisterError(RegisterError::AllocateFailure("test".to_string())),
-                e,
-            ),
-            _ => (),
+        assert_eq!(
+            Err(MachineError::RegisterError(RegisterError::AllocateFailure(
+                "test".to_string()
+            ))),
+            m.allocate_register("test")
+        );
+        for name in ["pc", "flag"].iter() {
+            assert_eq!(
+                Err(MachineError::RegisterError(RegisterError::AllocateFailure(
+                    name.to_string()
+                ))),
+                m.allocate_register(*name)
+            );
         }
     }
 
@@ -437,6 +1915,41 @@ mod machine_tests {
         assert_eq!(Ok(Value::new(2)), res);
     }
 
+    #[test]
+    fn test_machine_procedure() {
+        let procedures = vec![
+            Procedure::with_machine("stack-depth", 0, |m, _| {
+                Ok(m.stack().current_depth().to_value())
+            }),
+            Procedure::with_machine("register-count", 0, |m, _| {
+                Ok(m.total_registers().to_value())
+            }),
+        ];
+        let mut m = make_machine(
+            vec!["a", "depth", "count"],
+            &procedures,
+            r#"
+            (controller
+              (save a)
+              (save a)
+              (assign depth (op stack-depth))
+              (assign count (op register-count))
+              (perform (op initialize-stack)))
+            "#,
+        )
+        .unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("depth"));
+        assert_eq!(Ok(Value::new(5)), m.get_register_content("count"));
+        assert!(m.stack().is_empty());
+        assert!(matches!(
+            procedures[0].execute(vec![]),
+            Err(MachineError::ProcedureError(
+                ProcedureError::ExecuteFailure(_)
+            ))
+        ));
+    }
+
     #[test]
     fn test_install_procedures() {
         let mut procedures: Vec<Procedure> = vec![];
@@ -458,6 +1971,29 @@ mod machine_tests {
         assert_eq!(Ok(Value::new(1)), res);
     }
 
+    #[test]
+    fn test_operation_names() {
+        let mut m = Machine::new();
+        m.install_procedure(make_proc!("sub", 2, |a: i32, b: i32| a - b));
+        m.install_procedure(make_proc!("add", 2, |a: i32, b: i32| a + b));
+        assert_eq!(
+            vec![
+                "add".to_string(),
+                "current-time".to_string(),
+                "initialize-stack".to_string(),
+                "print-stack-statistics".to_string(),
+                "stack-top".to_string(),
+                "sub".to_string(),
+            ],
+            m.operation_names()
+        );
+        assert!(m.has_operation("add"));
+        assert!(m.has_operation("sub"));
+        assert!(m.has_operation("initialize-stack"));
+        assert!(m.has_operation("current-time"));
+        assert!(!m.has_operation("mul"));
+    }
+
     #[test]
     fn test_start_method() {
         let mut m = Machine::new();
@@ -465,6 +2001,1117 @@ mod machine_tests {
         assert_eq!(Ok("Done"), res);
     }
 
+    #[test]
+    fn test_execute_if_flag() {
+        let controller_text = r#"
+        (controller
+           (test (op =) (reg a) (const 1))
+           (if-flag (label one) (label other))
+         one
+           (assign b (const yes))
+           (goto (label done))
+         other
+           (assign b (const no))
+         done)
+        "#;
+        let procedures = vec![Procedure::new("=", 2, crate::math::equal)];
+        for (a, b) in [(1, "yes"), (2, "no")].iter() {
+            let mut m = crate::make_machine(vec!["a", "b"], &procedures, controller_text).unwrap();
+            m.set_register_content("a", *a).unwrap();
+            assert_eq!(Ok("Done"), m.start());
+            assert_eq!(Ok(Value::new(*b)), m.get_register_content("b"));
+        }
+    }
+
+    #[test]
+    fn test_execute_goto_if_eq() {
+        let controller_text = r#"
+        (controller
+           (goto-if-eq (reg a) (const 1) (label one))
+           (assign b (const other))
+           (goto (label done))
+         one
+           (assign b (const one))
+         done)
+        "#;
+        for (a, b) in [(1, "one"), (2, "other")].iter() {
+            let mut m = make_machine(vec!["a", "b"], &[], controller_text).unwrap();
+            m.set_register_content("a", *a).unwrap();
+            assert_eq!(Ok("Done"), m.start());
+            assert_eq!(Ok(Value::new(*b)), m.get_register_content("b"));
+        }
+    }
+
+    #[test]
+    fn test_execute_dispatch() {
+        let controller_text = r#"
+        (controller
+           (assign argl (const (1 2 3)))
+           (dispatch (reg op)))
+        "#;
+        let procedures = math::standard_procedures();
+        let mut m = make_machine(vec!["op", "argl", "val"], &procedures, controller_text).unwrap();
+        m.set_register_content("op", Value::Symbol("*".into()))
+            .unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(6)), m.get_register_content("val"));
+        m.set_register_content("op", Value::Symbol("mul".into()))
+            .unwrap();
+        assert_eq!(
+            Err(MachineError::ProcedureError(ProcedureError::NotFound(
+                "mul".into()
+            ))),
+            m.start()
+        );
+    }
+
+    #[test]
+    fn test_label_operation_args() {
+        let controller_text = r#"
+        (controller
+           (assign table (op list) (label first) (label second))
+           (goto (label done))
+         first
+           (assign table (const ()))
+         second
+           (assign table (const ()))
+         done)
+        "#;
+        let procedures = vec![Procedure::new("list", 0, |args: Vec<Value>| {
+            Value::List(args)
+        })];
+        let mut m = make_machine(vec!["table"], &procedures, controller_text).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(
+            Ok(Value::List(vec![Value::Pointer(2), Value::Pointer(3)])),
+            m.get_register_content("table")
+        );
+        let controller_text = "(controller (assign table (op list) (label nowhere)))";
+        assert!(make_machine(vec!["table"], &procedures, controller_text).is_err());
+    }
+
+    #[test]
+    fn test_nested_operations() {
+        let controller_text = r#"
+        (controller
+           (assign t (op +) (reg g) (op / (reg x) (reg g)))
+           (assign g (op /) (reg t) (const 2))
+           (goto-if-eq (op * (reg g) (const 2)) (const 5) (label done))
+           (assign g (const 0))
+         done)
+        "#;
+        let mut m =
+            make_machine_with_standard_ops(vec!["t", "g", "x"], &[], controller_text).unwrap();
+        m.set_register_content("g", 1).unwrap();
+        m.set_register_content("x", 4).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(5)), m.get_register_content("t"));
+        assert_eq!(Ok(Value::new(2.5)), m.get_register_content("g"));
+        let controller_text = "(controller (assign t (op +) (op sqrt (reg x))))";
+        assert_eq!(
+            Err(MachineError::ProcedureError(
+                ProcedureError::UnknownOperations(vec!["sqrt".into()])
+            )),
+            make_machine_with_standard_ops(vec!["t", "x"], &[], controller_text).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_goto_const() {
+        let controller_text = r#"
+        (controller
+           (goto (const skip))
+           (assign a (const 1))
+         skip
+           (assign b (const 2)))
+        "#;
+        let mut m = make_machine(vec!["a", "b"], &[], controller_text).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(
+            Ok(Value::Symbol("*unassigned*".into())),
+            m.get_register_content("a")
+        );
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("b"));
+        let controller_text = "(controller (goto (const nowhere)))";
+        let mut m = make_machine(vec![], &[], controller_text).unwrap();
+        assert_eq!(Err(MachineError::UnknownLabel("nowhere".into())), m.start());
+    }
+
+    #[test]
+    fn test_execute_tailcall() {
+        let procedures = math::standard_procedures();
+        let iterative = r#"
+        (controller
+           (assign product (const 1))
+         iter
+           (test (op =) (reg n) (const 0))
+           (branch (label done))
+           (assign product (op *) (reg product) (reg n))
+           (assign n (op -) (reg n) (const 1))
+           (tailcall (label iter))
+         done)
+        "#;
+        let mut m = make_machine(vec!["n", "product"], &procedures, iterative).unwrap();
+        m.set_register_content("n", 5).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(120)), m.get_register_content("product"));
+
+        let recursive = r#"
+        (controller
+         iter
+           (test (op =) (reg n) (const 0))
+           (branch (label done))
+           (save n)
+           (assign n (op -) (reg n) (const 1))
+           (tailcall (label iter))
+         done)
+        "#;
+        let mut m = make_machine(vec!["n"], &procedures, recursive).unwrap();
+        m.set_register_content("n", 5).unwrap();
+        assert_eq!(
+            Err(MachineError::NotTailCall {
+                label: "iter".into(),
+                expected: 1,
+                got: 2,
+            }),
+            m.start()
+        );
+        m.set_tail_call_check(false);
+        m.reset_stack();
+        m.set_register_content("n", 5).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let controller_text = r#"
+        (controller
+         test-b
+           (test (op =) (reg b) (const 0))
+           (branch (label gcd-done))
+           (assign t (reg a))
+         rem-loop
+           (test (op <) (reg t) (reg b))
+           (branch (label rem-done))
+           (assign t (op -) (reg t) (reg b))
+           (goto (label rem-loop))
+         rem-done
+           (assign a (reg b))
+           (assign b (reg t))
+           (goto (label test-b))
+         gcd-done)
+        "#;
+        let m = make_machine(
+            vec!["a", "b", "t"],
+            &math::standard_procedures(),
+            controller_text,
+        )
+        .unwrap();
+        let dot = m.to_dot();
+        assert!(dot.starts_with("digraph controller {\n"));
+        assert!(dot.ends_with("\n}"));
+        let edges: Vec<&str> = dot.lines().filter(|line| line.contains("->")).collect();
+        assert_eq!(
+            vec![
+                r#"    "controller" -> "test-b" [label="fall-through"];"#,
+                r#"    "test-b" -> "gcd-done" [label="branch"];"#,
+                r#"    "test-b" -> "rem-loop" [label="fall-through"];"#,
+                r#"    "rem-loop" -> "rem-done" [label="branch"];"#,
+                r#"    "rem-loop" -> "rem-loop" [label="goto"];"#,
+                r#"    "rem-done" -> "test-b" [label="goto"];"#,
+            ],
+            edges
+        );
+    }
+
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace_to() {
+        let buffer = Arc::new(Mutex::new(vec![]));
+        let mut m = fibonacci_machine(5);
+        m.trace_to(Box::new(SharedBuffer(buffer.clone())));
+        assert_eq!(Ok("Done"), m.start());
+        let transcript = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = transcript.lines().collect();
+        assert_eq!(m.run_stats().instructions, lines.len() as u64);
+        assert_eq!(
+            "0000 controller: (assign continue (label fib-done))",
+            lines[0]
+        );
+        assert_eq!("0001 fib-loop: (test (op <) (reg n) (const 2))", lines[1]);
+    }
+
+    #[test]
+    fn test_pc_hook() {
+        let mut m = gcd_machine(206, 40);
+        let pcs = Arc::new(Mutex::new(vec![]));
+        let recorded = pcs.clone();
+        m.set_pc_hook(Box::new(move |pc| recorded.lock().unwrap().push(pc)));
+        let buffer = Arc::new(Mutex::new(vec![]));
+        m.trace_to(Box::new(SharedBuffer(buffer.clone())));
+        assert_eq!(Ok("Done"), m.start());
+        let transcript = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let executed: Vec<usize> = transcript
+            .lines()
+            .map(|line| line[..4].parse().unwrap())
+            .collect();
+        // The last pc is past the end of the controller, and isn't executed.
+        let pcs = pcs.lock().unwrap().clone();
+        assert_eq!((&6, &executed[..]), pcs.split_last().unwrap());
+    }
+
+    #[test]
+    fn test_require_explicit_halt() {
+        let falling_off = "(controller (assign a (const 1)))";
+        let mut m = make_machine(vec!["a"], &[], falling_off).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        m.set_require_explicit_halt(true);
+        assert_eq!(Err(MachineError::FellOffEnd), m.start());
+        assert_eq!(Ok(Value::new(1)), m.get_register_content("a"));
+
+        let halting = "(controller (assign a (const 1)) (goto (label done)) done)";
+        let mut m = make_machine(vec!["a"], &[], halting).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        m.set_require_explicit_halt(true);
+        assert_eq!(Ok("Done"), m.start());
+    }
+
+    #[test]
+    fn test_breakpoint_at_first_instruction() {
+        let mut m = fibonacci_machine(3);
+        m.set_breakpoint("controller");
+        assert_eq!(
+            Ok(RunState::Paused {
+                label: "controller".into()
+            }),
+            m.proceed()
+        );
+        assert_eq!(Value::Pointer(0), m.pc.get());
+        assert_eq!(Ok(RunState::Done), m.proceed());
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("val"));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut m = fibonacci_machine(3);
+        m.set_breakpoint("fib-loop");
+        let paused = Ok(RunState::Paused {
+            label: "fib-loop".into(),
+        });
+        // Falling through from the first instruction reaches the breakpoint.
+        assert_eq!(paused, m.proceed());
+        assert_eq!(Value::Pointer(1), m.pc.get());
+        assert_eq!(Some(&Value::new(3)), m.dump_registers().get("n"));
+        // Then going to it from the recursive call does.
+        assert_eq!(paused, m.proceed());
+        assert_eq!(Some(&Value::new(2)), m.dump_registers().get("n"));
+        m.clear_breakpoint("fib-loop");
+        assert_eq!(Ok(RunState::Done), m.proceed());
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("val"));
+    }
+
+    #[test]
+    fn test_trace_hook() {
+        let mut m = gcd_machine(12, 8);
+        let traced = Arc::new(Mutex::new(vec![]));
+        let recorded = traced.clone();
+        m.set_trace_hook(Box::new(move |inst, snapshot| {
+            let b = snapshot.register("b").unwrap();
+            recorded
+                .lock()
+                .unwrap()
+                .push(format!("{:04} {} b={}", snapshot.pc(), inst, b));
+        }));
+        assert_eq!(Ok("Done"), m.start());
+        let traced = traced.lock().unwrap();
+        assert_eq!(
+            vec![
+                "0000 (test (op =) (reg b) (const 0)) b=8",
+                "0001 (branch (label gcd-done)) b=8",
+                "0002 (assign t (op rem) (reg a) (reg b)) b=8",
+                "0003 (assign a (reg b)) b=8",
+                "0004 (assign b (reg t)) b=8",
+                "0005 (goto (label test-b)) b=4",
+            ],
+            traced[..6].to_vec()
+        );
+        assert_eq!(m.run_stats().instructions, traced.len() as u64);
+        assert_eq!(
+            Some(&"0001 (branch (label gcd-done)) b=0".to_string()),
+            traced.last()
+        );
+    }
+
+    #[test]
+    fn test_trace_register() {
+        let mut m = fibonacci_machine(3);
+        let writes = Arc::new(Mutex::new(vec![]));
+        let recorded = writes.clone();
+        m.set_register_trace_hook(Box::new(move |name, old, new| {
+            recorded
+                .lock()
+                .unwrap()
+                .push(format!("{}: {} -> {}", name, old, new));
+        }));
+        m.trace_register("val");
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(
+            vec![
+                "val: *unassigned* -> 1",
+                "val: 1 -> 0",
+                "val: 0 -> 1",
+                "val: 1 -> 1",
+                "val: 1 -> 1",
+                "val: 1 -> 1",
+                "val: 1 -> 2",
+            ],
+            *writes.lock().unwrap()
+        );
+        m.untrace_register("val");
+        m.set_register_content("n", 3).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(7, writes.lock().unwrap().len());
+    }
+
+    #[test]
+    fn test_jump_targets() {
+        let mut m = fibonacci_machine(10);
+        assert!(m.jump_targets().is_empty());
+        assert_eq!(Ok("Done"), m.start());
+        let targets = m.jump_targets();
+        assert_eq!("fib-loop", targets[0].0);
+        assert!(targets.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        let total: u64 = targets.iter().map(|(_, count)| count).sum();
+        assert_eq!(m.run_stats().jumps, total);
+    }
+
+    #[test]
+    fn test_operation_call_counts() {
+        let controller_text = r#"
+        (controller
+         test-b
+           (test (op =) (reg b) (const 0))
+           (branch (label gcd-done))
+           (assign t (reg a))
+         rem-loop
+           (test (op <) (reg t) (reg b))
+           (branch (label rem-done))
+           (assign t (op -) (reg t) (reg b))
+           (goto (label rem-loop))
+         rem-done
+           (assign a (reg b))
+           (assign b (reg t))
+           (goto (label test-b))
+         gcd-done)
+        "#;
+        let mut m = make_machine(
+            vec!["a", "b", "t"],
+            &math::standard_procedures(),
+            controller_text,
+        )
+        .unwrap();
+        for _ in 0..2 {
+            m.set_register_content("a", 6).unwrap();
+            m.set_register_content("b", 4).unwrap();
+            assert_eq!(Ok("Done"), m.start());
+            // (6 4) -> (4 2) -> (2 0), with the remainders 6 - 4 and 4 - 2 - 2.
+            assert_eq!(
+                vec![("<".to_string(), 5), ("-".into(), 3), ("=".into(), 3)],
+                m.operation_call_counts()
+            );
+        }
+    }
+
+    #[test]
+    fn test_instruction_stats() {
+        let mut m = fibonacci_machine(5);
+        assert_eq!(Ok("Done"), m.start());
+        let stats = m.instruction_stats();
+        assert_eq!(m.run_stats().instructions, stats.total());
+        assert_eq!(stats.save, stats.restore);
+        assert_eq!(stats.test, stats.branch);
+        assert_eq!(m.stack().total_pushes() as u64, stats.save);
+        assert_eq!(0, stats.perform);
+        assert_eq!(0, stats.if_flag);
+    }
+
+    #[test]
+    fn test_run_with_limit() {
+        let mut m = fibonacci_machine(10);
+        assert_eq!(
+            Err(MachineError::StepLimitExceeded(10)),
+            m.run_with_limit(10)
+        );
+        assert_eq!(10, m.run_stats().instructions);
+        assert_eq!(Ok(Value::new(9)), m.get_register_content("n"));
+        let mut m = fibonacci_machine(3);
+        assert_eq!(Ok("Done"), m.run_with_limit(1000));
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("val"));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let controller_text = r#"
+        (controller
+         test-b
+           (test (op =) (reg b) (const 0))
+           (branch (label gcd-done))
+           (assign t (op rem) (reg a) (reg b))
+           (assign a (reg b))
+           (assign b (reg t))
+           (goto (label test-b))
+         gcd-done)
+        "#;
+        let procedures = math::standard_procedures();
+        let m = make_machine(vec!["a", "b", "t"], &procedures, controller_text).unwrap();
+        let listing = m.disassemble();
+        assert_eq!(
+            "controller:
+test-b:
+0000  test (op =) (reg b) (const 0)
+0001  branch (label gcd-done)
+0002  assign t (op rem) (reg a) (reg b)
+0003  assign a (reg b)
+0004  assign b (reg t)
+0005  goto (label test-b)
+gcd-done:
+",
+            listing
+        );
+    }
+
+    #[test]
+    fn test_set_flag() {
+        let controller_text = r#"
+        (controller
+           (branch (label yes))
+           (assign a (const no))
+           (goto (label done))
+         yes
+           (assign a (const yes))
+         done)
+        "#;
+        let mut m = make_machine(vec!["a"], &[], controller_text).unwrap();
+        m.set_flag(true);
+        assert_eq!(Value::Boolean(true), m.flag());
+        assert_eq!(Ok(true), m.step());
+        assert_eq!(1, m.run_stats().jumps);
+        assert_eq!(Ok(true), m.step());
+        assert_eq!(Ok(Value::new("yes")), m.get_register_content("a"));
+        assert_eq!(Ok(false), m.step());
+    }
+
+    #[test]
+    fn test_run_with_input() {
+        let mut m = make_machine(
+            vec!["continue", "n", "val"],
+            &fibonacci_procedures(),
+            FIBONACCI_LOOP,
+        )
+        .unwrap();
+        let read = m.procedures()["read"].clone();
+        let output = m.run_with_input("5\nq\n").unwrap();
+        assert!(output.lines().any(|line| line == "5"));
+        assert!(read == m.procedures()["read"]);
+        // Running out of input fails, and still restores the procedures.
+        assert_eq!(
+            Err(ProcedureError::ExecuteFailure("read: no more input".into()).into()),
+            m.run_with_input("5\n")
+        );
+        assert!(read == m.procedures()["read"]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let controller_text = r#"
+        (controller
+           (assign a (op +) (reg b) (const 1))
+           (test (op <) (reg a))
+           (branch (label nowhere))
+           (save a)
+           (perform (op ad) (reg a) (reg b))
+         done)
+        "#;
+        let procedures = vec![
+            Procedure::new("+", 2, math::addition),
+            Procedure::new("<", 2, math::less_than),
+        ];
+        // `make_machine` rejects unknown labels, so the controller is installed directly.
+        let (insts, labels) = extract_labels(controller_text).unwrap();
+        let mut m = make_machine(vec!["a", "b"], &procedures, "(controller)").unwrap();
+        m.install_instructions(insts);
+        m.install_labels(labels);
+        let warning = |index, message: &str| Diagnostic {
+            severity: Severity::Warning,
+            index,
+            message: message.into(),
+        };
+        let error = |index, message: &str| Diagnostic {
+            severity: Severity::Error,
+            index,
+            message: message.into(),
+        };
+        assert_eq!(
+            vec![
+                warning(Some(0), "Register b may be read before written"),
+                error(Some(1), "Operation < expected 2 arguments, got 1"),
+                error(Some(2), "Unknown label: nowhere"),
+                error(Some(4), "Unknown operation: ad"),
+                warning(None, "Unbalanced stack: 1 saves, 0 restores"),
+            ],
+            m.validate()
+        );
+        assert!(fibonacci_machine(3).validate().is_empty());
+    }
+
+    #[test]
+    fn test_check_operations() {
+        let controller_text = r#"
+        (controller
+           (assign a (op ad) (reg a) (const 1))
+           (perform (op print-stack-statistics))
+           (test (op =) (reg a) (const 1))
+           (assign a (op ad) (reg a) (const 2))
+           (assign a (op sub) (reg a) (const 1)))
+        "#;
+        let procedures = math::standard_procedures();
+        assert_eq!(
+            Err(MachineError::ProcedureError(
+                ProcedureError::UnknownOperations(vec!["ad".into(), "sub".into()])
+            )),
+            make_machine(vec!["a"], &procedures, controller_text).map(|_| ())
+        );
+        assert_eq!(Ok(()), fibonacci_machine(3).check_operations());
+    }
+
+    #[test]
+    fn test_float_epsilon() {
+        let controller_text = r#"
+        (controller
+           (assign x (op +) (const 0.1) (const 0.2))
+           (test (op =) (reg x) (const 0.3)))
+        "#;
+        let mut m = make_machine(vec!["x"], &math::standard_procedures(), controller_text).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Value::Boolean(false), m.flag());
+        m.set_float_epsilon(1e-9);
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Value::Boolean(true), m.flag());
+        m.set_float_epsilon(0.0);
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Value::Boolean(false), m.flag());
+    }
+
+    #[test]
+    fn test_integer_overflow_mode() {
+        let controller_text = r#"
+        (controller
+           (assign x (op *) (reg x) (const 2) (const 1)))
+        "#;
+        let mut m = make_machine(vec!["x"], &math::standard_procedures(), controller_text).unwrap();
+        let run = |m: &mut Machine| {
+            m.set_register_content("x", i64::MAX).unwrap();
+            m.start().and_then(|_| m.get_register_content("x"))
+        };
+        assert_eq!(Ok(Value::Num(i64::MAX as f64 * 2.0)), run(&mut m));
+        m.set_integer_overflow_mode(Some(IntegerOverflowMode::Checked));
+        assert_eq!(
+            Err(MachineError::IntegerOverflow(format!("{} * 2", i64::MAX))),
+            run(&mut m)
+        );
+        m.set_integer_overflow_mode(Some(IntegerOverflowMode::Wrapping));
+        assert_eq!(Ok(Value::Integer(-2)), run(&mut m));
+        m.set_integer_overflow_mode(Some(IntegerOverflowMode::Saturating));
+        assert_eq!(Ok(Value::Integer(i64::MAX)), run(&mut m));
+        // An operation installed afterwards is called as it is.
+        m.install_procedure(Procedure::new("*", 0, |_| 0));
+        assert_eq!(Ok(Value::Integer(0)), run(&mut m));
+        m.set_integer_overflow_mode(None);
+        assert_eq!(Ok(Value::Num(i64::MAX as f64 * 2.0)), run(&mut m));
+    }
+
+    #[test]
+    fn test_typed_read() {
+        let controller_text = r#"
+        (controller
+           (assign n (op read-number) (const "n: "))
+           (assign s (op read-line))
+           (perform (op print) (reg n))
+           (perform (op print) (reg s))
+           (perform (op write) (reg s)))
+        "#;
+        let mut m = make_machine(vec!["n", "s"], &[], controller_text).unwrap();
+        let output = m.run_with_input("abc\n42\n (raw text)\n").unwrap();
+        assert_eq!("n: n: 42\n (raw text)\n\" (raw text)\"\n", output);
+        assert_eq!(Ok(Value::Num(42.0)), m.get_register_content("n"));
+        assert_eq!(
+            Ok(Value::String(" (raw text)".into())),
+            m.get_register_content("s")
+        );
+    }
+
+    #[test]
+    fn test_current_time() {
+        let controller_text = r#"
+        (controller
+           (assign t (op current-time)))
+        "#;
+        let mut m = make_machine(vec!["t"], &[], controller_text).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        let elapsed = Duration::try_from(&m.get_register_content("t").unwrap()).unwrap();
+        assert!(elapsed <= m.run_stats().duration);
+    }
+
+    #[test]
+    fn test_make_machine_with_standard_ops() {
+        let controller_text = r#"
+        (controller
+           (assign a (op +) (reg a) (const 1))
+           (assign b (op modulo) (reg a) (const 4)))
+        "#;
+        let mut m =
+            crate::make_machine_with_standard_ops(vec!["a", "b"], &[], controller_text).unwrap();
+        m.set_register_content("a", 6).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(7)), m.get_register_content("a"));
+        assert_eq!(Ok(Value::new(3)), m.get_register_content("b"));
+    }
+
+    #[test]
+    fn test_continuation() {
+        let controller_text = r#"
+        (controller
+           (assign i (const 0))
+         loop
+           (test (op =) (reg i) (const 3))
+           (branch (label done))
+           (save i)
+           (assign i (op +) (reg i) (const 1))
+           (goto (label loop))
+         done)
+        "#;
+        let procedures = vec![
+            Procedure::new("=", 2, math::equal),
+            Procedure::new("+", 2, math::addition),
+        ];
+        let mut m = make_machine(vec!["i"], &procedures, controller_text).unwrap();
+        while m.get_register_content("i") != Ok(Value::new(1)) {
+            assert_eq!(Ok(true), m.step());
+        }
+        let continuation = m.save_continuation();
+        assert_eq!(Ok("Done"), m.execute());
+        assert_eq!(Ok(Value::new(3)), m.get_register_content("i"));
+        for _ in 0..2 {
+            m.restore_continuation(&continuation);
+            assert_eq!(Ok(Value::new(1)), m.get_register_content("i"));
+            assert_eq!(Ok(Value::new(0)), m.stack.clone().pop());
+            assert_eq!(Ok("Done"), m.execute());
+            assert_eq!(Ok(Value::new(3)), m.get_register_content("i"));
+        }
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut m = make_machine(
+            vec!["a", "b"],
+            &[Procedure::new("+", 0, math::addition)],
+            r#"
+            (controller
+              (assign a (const 1))
+              (save a)
+              (assign b (op +) (reg a) (const 1))
+              (restore a)
+              (assign a (op +) (reg a) (reg b)))
+            "#,
+        )
+        .unwrap();
+        m.enable_history(2);
+        assert!(!m.step_back());
+        assert_eq!(Ok(true), m.step());
+        assert_eq!(Ok(true), m.step());
+        assert_eq!(Ok(true), m.step());
+        let before_restore = m.dump_registers();
+        assert_eq!(Ok(true), m.step());
+        assert_eq!(Ok(true), m.step());
+        assert_eq!(Ok(Value::new(3)), m.get_register_content("a"));
+        assert!(m.step_back());
+        assert!(m.step_back());
+        assert_eq!(before_restore, m.dump_registers());
+        assert_eq!(1, m.stack().current_depth());
+        // The older states were dropped.
+        assert!(!m.step_back());
+        assert_eq!(Ok("Done"), m.execute());
+        assert_eq!(Ok(Value::new(3)), m.get_register_content("a"));
+
+        // A restart doesn't rewind into the previous run.
+        m.enable_history(10);
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok("Done"), m.start());
+        for _ in 0..5 {
+            assert!(m.step_back());
+        }
+        assert!(!m.step_back());
+        assert_eq!(Value::Pointer(0), m.pc.get());
+        assert_eq!(Ok("Done"), m.start());
+        m.reset();
+        assert!(!m.step_back());
+    }
+
+    #[test]
+    fn test_stack_top() {
+        let mut m = make_machine(
+            vec!["a", "top"],
+            &[],
+            r#"
+            (controller
+              (assign a (const 1))
+              (save a)
+              (assign a (const 2))
+              (assign top (op stack-top))
+              (restore a)
+              (assign top (op stack-top)))
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            Err(MachineError::StackError(
+                "try to peek an empty stack".to_string()
+            )),
+            m.start()
+        );
+        assert_eq!(Ok(Value::new(1)), m.get_register_content("a"));
+        assert_eq!(Ok(Value::new(1)), m.get_register_content("top"));
+    }
+
+    #[test]
+    fn test_stack_top_with_per_register_stacks() {
+        let controller = r#"
+        (controller
+          (assign a (const 1))
+          (save a)
+          (assign b (const 2))
+          (save b)
+          (assign top (op stack-top) (const a))
+          (assign top (op stack-top)))
+        "#;
+        let mut m = make_machine(vec!["a", "b", "top"], &[], controller).unwrap();
+        m.use_per_register_stacks(true);
+        assert_eq!(
+            Err(MachineError::StackError(
+                "stack-top needs a register name with per-register stacks".into()
+            )),
+            m.start()
+        );
+        assert_eq!(Ok(Value::new(1)), m.get_register_content("top"));
+        assert_eq!(Ok(&Value::new(2)), m.register_stack("b").unwrap().peek());
+        assert_eq!(
+            Err(MachineError::StackError("no saved contents of top".into())),
+            m.call_procedure("stack-top", vec![Value::Symbol("top".into())])
+        );
+    }
+
+    #[test]
+    fn test_per_register_stacks() {
+        let mut shared = fibonacci_machine(10);
+        assert_eq!(Ok("Done"), shared.start());
+        let mut m = fibonacci_machine(10);
+        m.use_per_register_stacks(true);
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(55)), m.get_register_content("val"));
+        assert_eq!(shared.run_stats().total_pushes, m.run_stats().total_pushes);
+        assert!(m.stack().is_empty());
+        for reg_name in ["continue", "n", "val"].iter() {
+            let stack = m.register_stack(reg_name).unwrap();
+            assert!(stack.total_pushes() > 0);
+            assert!(stack.is_empty());
+        }
+        assert!(m.register_stack("a").is_none());
+
+        let controller = r#"
+        (controller
+          (assign a (const 1))
+          (save a)
+          (restore b))
+        "#;
+        let mut m = make_machine(vec!["a", "b"], &[], controller).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(1)), m.get_register_content("b"));
+        m.use_per_register_stacks(true);
+        assert_eq!(
+            Err(MachineError::StackError("no saved contents of b".into())),
+            m.start()
+        );
+    }
+
+    #[test]
+    fn test_register_history() {
+        let controller_text = r#"
+        (controller
+           (assign g (const 1.0))
+         test-g
+           (assign t (op *) (reg g) (reg g))
+           (assign t (op -) (reg t) (reg x))
+           (assign t (op abs) (reg t))
+           (test (op <) (reg t) (const 0.001))
+           (branch (label sqrt-done))
+           (assign t (op /) (reg x) (reg g))
+           (assign t (op +) (reg g) (reg t))
+           (assign g (op /) (reg t) (const 2.0))
+           (goto (label test-g))
+         sqrt-done)
+        "#;
+        let mut m =
+            make_machine_with_standard_ops(vec!["g", "t", "x"], &[], controller_text).unwrap();
+        assert_eq!(None, m.register_history("g"));
+        m.track_register("g");
+        m.set_register_content("x", 2).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        let history: Vec<f64> = m
+            .register_history("g")
+            .unwrap()
+            .iter()
+            .map(|g| f64::try_from(g).unwrap())
+            .collect();
+        assert_eq!(vec![1.0, 1.5], history[..2].to_vec());
+        let errors: Vec<f64> = history
+            .iter()
+            .map(|g| (g - std::f64::consts::SQRT_2).abs())
+            .collect();
+        assert!(errors.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(*errors.last().unwrap() < 1e-3);
+        assert_eq!(None, m.register_history("t"));
+        // Restarting clears the history.
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(history.len(), m.register_history("g").unwrap().len());
+    }
+
+    #[test]
+    fn test_operation_timeout() {
+        let procedures = vec![
+            Procedure::new("slow", 0, |_| {
+                thread::sleep(Duration::from_millis(200));
+            }),
+            Procedure::new("+", 0, math::addition),
+        ];
+        let mut m = make_machine(
+            vec!["a"],
+            &procedures,
+            r#"
+            (controller
+              (assign a (op +) (const 1) (const 2))
+              (perform (op initialize-stack))
+              (perform (op slow))
+              (assign a (const 0)))
+            "#,
+        )
+        .unwrap();
+        m.set_operation_timeout(Some(Duration::from_millis(50)));
+        assert_eq!(
+            Err(MachineError::OperationTimeout("slow".into())),
+            m.start()
+        );
+        assert_eq!(Ok(Value::new(3)), m.get_register_content("a"));
+        m.set_operation_timeout(None);
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(0)), m.get_register_content("a"));
+    }
+
+    #[test]
+    fn test_template() {
+        let mut m = fibonacci_machine(3);
+        m.set_stack_limit(100);
+        assert_eq!(Ok("Done"), m.start());
+        let template = m.template();
+        assert_eq!(
+            Ok(Value::Symbol("*unassigned*".into())),
+            template.get_register_content("n")
+        );
+        assert!(template.stack().is_empty());
+        let workers: Vec<_> = [10, 15]
+            .iter()
+            .map(|&n| {
+                let mut m = template.template();
+                std::thread::spawn(move || {
+                    m.set_register_content("n", n).unwrap();
+                    m.start().map(|_| m.get_register_content("val"))
+                })
+            })
+            .collect();
+        let results: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        assert_eq!(
+            vec![Ok(Ok(Value::new(55))), Ok(Ok(Value::new(610)))],
+            results
+        );
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("val"));
+        assert_eq!(Some(100), template.stack().max_depth_limit());
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        let mut m = make_machine(
+            vec!["n"],
+            &[Procedure::new("+", 0, math::addition)],
+            r#"
+            (controller
+              (assign n (const 0))
+             loop
+              (save n)
+              (assign n (op +) (reg n) (const 1))
+              (goto (label loop)))
+            "#,
+        )
+        .unwrap();
+        m.set_stack_limit(100);
+        assert_eq!(Err(MachineError::StackOverflow { depth: 100 }), m.start());
+        assert_eq!(Ok(Value::new(100)), m.get_register_content("n"));
+        assert_eq!(100, m.stack().current_depth());
+    }
+
+    #[test]
+    fn test_merge_procedures() {
+        let mut m = Machine::new();
+        let base = vec![
+            Procedure::new("+", 0, math::addition),
+            Procedure::new("-", 1, math::subtraction),
+        ];
+        assert!(m.merge_procedures(&base).is_empty());
+        let domain = vec![
+            make_proc!("+", |_| "overridden"),
+            Procedure::new("*", 0, math::multiplication),
+        ];
+        assert_eq!(vec!["+".to_string()], m.merge_procedures(&domain));
+        assert_eq!(7, m.procedures().len());
+        assert_eq!(Ok(Value::new("overridden")), m.call_procedure("+", vec![]));
+        assert_eq!(
+            Ok(Value::new(1)),
+            m.call_procedure("-", vec![Value::new(3), Value::new(2)])
+        );
+    }
+
+    #[test]
+    fn test_granular_resets() {
+        let unassigned = Value::Symbol("*unassigned*".into());
+        let setup = || {
+            let mut m = make_machine(vec!["a", "b"], &[], "(controller)").unwrap();
+            m.set_register_content("a", 1).unwrap();
+            m.set_register_content("b", 2).unwrap();
+            m.stack.push(3).unwrap();
+            m.set_flag(true);
+            m
+        };
+
+        let mut m = setup();
+        m.reset_registers();
+        assert_eq!(Ok(unassigned.clone()), m.get_register_content("a"));
+        assert_eq!(Ok(unassigned.clone()), m.get_register_content("b"));
+        assert!(!m.stack().is_empty());
+        assert_eq!(Value::Boolean(true), m.flag());
+
+        let mut m = setup();
+        m.reset_stack();
+        assert!(m.stack().is_empty());
+        assert_eq!(Ok(Value::new(1)), m.get_register_content("a"));
+        assert_eq!(Value::Boolean(true), m.flag());
+
+        let mut m = setup();
+        m.reset_flag();
+        assert_eq!(unassigned, m.flag());
+        assert!(!m.stack().is_empty());
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("b"));
+
+        let mut m = setup();
+        m.reset_registers_except(&["b"]);
+        assert_eq!(Ok(unassigned.clone()), m.get_register_content("a"));
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("b"));
+
+        let mut m = setup();
+        m.reset();
+        assert_eq!(Ok(unassigned.clone()), m.get_register_content("a"));
+        assert!(m.stack().is_empty());
+        assert_eq!(unassigned, m.flag());
+    }
+
+    #[test]
+    fn test_rerun_after_reset() {
+        let mut m = gcd_machine(0, 0);
+        for &(a, b, gcd) in [(206, 40, 2), (48, 18, 6)].iter() {
+            m.reset();
+            assert_eq!(Value::Pointer(0), m.pc.get());
+            m.set_register_content("a", a).unwrap();
+            m.set_register_content("b", b).unwrap();
+            assert_eq!(Ok(true), m.step());
+            assert_eq!(Ok("Done"), m.execute());
+            assert_eq!(Ok(Value::new(gcd)), m.get_register_content("a"));
+        }
+    }
+
+    #[test]
+    fn test_estimated_recursion_depth() {
+        let mut m = fibonacci_machine(5);
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(8, m.stack().maximum_depth());
+        // Saves `continue` and `n`, or `continue` and `val` in each call.
+        assert_eq!(Some(4), m.estimated_recursion_depth(2));
+        assert_eq!(None, m.estimated_recursion_depth(0));
+    }
+
+    #[test]
+    fn test_coverage() {
+        let controller_text = r#"
+        (controller
+           (assign a (const 1))
+           (goto (label done))
+         unreachable
+           (assign a (const 2))
+           (assign a (const 3))
+         done
+           (assign a (op +) (reg a) (const 1)))
+        "#;
+        let procedures = vec![Procedure::new("+", 2, math::addition)];
+        let mut m = make_machine(vec!["a"], &procedures, controller_text).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(&[1, 1, 0, 0, 1], m.instruction_counts());
+        assert_eq!(
+            vec![(0, true), (1, true), (2, false), (3, false), (4, true)],
+            m.coverage()
+        );
+        assert_eq!(60.0, m.coverage_percentage());
+    }
+
+    #[test]
+    fn test_run_stats() {
+        let mut m = fibonacci_machine(3);
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::new(2)), m.get_register_content("val"));
+        // Fib(3) enters `fib-loop` 5 times: 2 recursive cases and 3 base cases.
+        // Each recursive case executes 19 instructions, makes 4 pushes and 3 jumps,
+        // and each base case executes 4 instructions and makes 2 jumps.
+        let stats = m.run_stats();
+        assert_eq!(1 + 2 * 19 + 3 * 4, stats.instructions);
+        assert_eq!(2 * 4, stats.total_pushes);
+        assert_eq!(4, stats.maximum_depth);
+        assert_eq!(2 * 3 + 3 * 2, stats.jumps);
+        assert!(stats.duration < Duration::from_secs(1));
+        // A restart counts the pushes of the new run only.
+        m.set_register_content("n", 3).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        let rerun = m.run_stats();
+        assert_eq!(stats.instructions, rerun.instructions);
+        assert_eq!(stats.total_pushes, rerun.total_pushes);
+        assert_eq!(stats.maximum_depth, rerun.maximum_depth);
+    }
+
     #[test]
     fn test_advance_pc() {
         let mut m = Machine::new();
@@ -475,6 +3122,38 @@ mod machine_tests {
         assert_eq!(Value::Pointer(1), actual);
     }
 
+    #[test]
+    fn test_dump_registers() {
+        let mut m = fibonacci_machine(5);
+        assert_eq!(vec!["continue", "n", "val"], m.register_names());
+        assert_eq!(Ok("Done"), m.start());
+        let dump = m.dump_registers();
+        assert_eq!(5, dump.len());
+        assert_eq!(Some(&Value::new(5)), dump.get("val"));
+        assert_eq!(Some(&Value::Pointer(m.the_inst_seq.len())), dump.get("pc"));
+        assert_eq!(Some(&m.flag()), dump.get("flag"));
+    }
+
+    #[test]
+    fn test_copy_registers_into() {
+        let mut fib = fibonacci_machine(6);
+        assert_eq!(Ok("Done"), fib.start());
+        let mut double = make_machine(
+            vec!["val"],
+            &[Procedure::new("+", 2, math::addition)],
+            "(controller (assign val (op +) (reg val) (reg val)))",
+        )
+        .unwrap();
+        assert_eq!(Ok(()), fib.copy_registers_into(&mut double, &["val"]));
+        assert_eq!(Ok("Done"), double.start());
+        assert_eq!(Ok(Value::new(16)), double.get_register_content("val"));
+        assert_eq!(
+            Err(RegisterError::LookupFailure("n".into()).into()),
+            fib.copy_registers_into(&mut double, &["val", "n"])
+        );
+        assert_eq!(Ok(Value::new(16)), double.get_register_content("val"));
+    }
+
     #[test]
     fn test_manipulate_register_content() {
         let mut m = Machine::new();
@@ -1,8 +1,15 @@
+#[allow(clippy::module_inception)]
 mod machine;
 mod register;
 mod stack;
 
 pub mod errors;
 pub mod procedure;
+pub mod promise;
 pub mod value;
-pub use machine::Machine;
+#[cfg(test)]
+pub(crate) use machine::machine_tests::{fibonacci_procedures, gcd_machine, FIBONACCI_LOOP, GCD};
+pub use machine::{
+    Continuation, InstructionStats, Machine, MachineSnapshot, MachineState, RegisterTraceHook,
+    RunState, RunStats, TraceHook,
+};
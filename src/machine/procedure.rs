@@ -5,12 +5,18 @@ use std::sync::Arc;
 
 use super::errors::{MResult, ProcedureError};
 use super::value::{ToValue, Value};
+use super::Machine;
+
+type MachineFn = Arc<dyn Fn(&mut Machine, Vec<Value>) -> MResult<Value> + Send + Sync>;
 
 /// Procedure for a `Fn(Vec<Value>) -> MResult<Value>` to be executed
 pub struct Procedure {
     name: String,
-    proc: Arc<dyn Fn(Vec<Value>) -> Value + Send + Sync>,
+    proc: Arc<dyn Fn(Vec<Value>) -> MResult<Value> + Send + Sync>,
+    /// The function receiving the machine, for a procedure made by `with_machine`.
+    machine_proc: Option<MachineFn>,
     min_arg_num: usize,
+    max_arg_num: Option<usize>,
 }
 
 impl Procedure {
@@ -22,8 +28,80 @@ impl Procedure {
     {
         Self {
             name: name.into(),
-            proc: Arc::new(move |args: Vec<Value>| f(args).to_value()),
+            proc: Arc::new(move |args: Vec<Value>| Ok(f(args).to_value())),
+            machine_proc: None,
+            min_arg_num: num,
+            max_arg_num: None,
+        }
+    }
+
+    /// Constructs a procedure taking `min` to `max` arguments,
+    /// `execute` fails with `ArgsTooMany` for more.
+    pub fn new_ranged<F, S, R>(name: S, min: usize, max: usize, f: F) -> Self
+    where
+        F: Fn(Vec<Value>) -> R + Send + Sync + 'static,
+        R: ToValue,
+        S: Into<String>,
+    {
+        Self::new(name, min, f).with_max_args(max)
+    }
+
+    /// Limits the procedure to at most `max` arguments.
+    pub fn with_max_args(mut self, max: usize) -> Self {
+        self.max_arg_num = Some(max);
+        self
+    }
+
+    /// Constructs a procedure whose function may fail, the error is returned by `execute`.
+    pub fn fallible<F, S, R>(name: S, num: usize, f: F) -> Self
+    where
+        F: Fn(Vec<Value>) -> MResult<R> + Send + Sync + 'static,
+        R: ToValue,
+        S: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            proc: Arc::new(move |args: Vec<Value>| f(args).map(ToValue::to_value)),
+            machine_proc: None,
             min_arg_num: num,
+            max_arg_num: None,
+        }
+    }
+
+    /// Constructs a procedure from a function returning the `MResult` of `execute` as is.
+    pub fn try_new<F, S>(name: S, num: usize, f: F) -> Self
+    where
+        F: Fn(Vec<Value>) -> MResult<Value> + Send + Sync + 'static,
+        S: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            proc: Arc::new(f),
+            machine_proc: None,
+            min_arg_num: num,
+            max_arg_num: None,
+        }
+    }
+
+    /// Constructs a procedure whose function also receives the machine applying it,
+    /// e.g. to inspect the stack or the registers, like `print-stack-statistics`.
+    /// The machine is borrowed mutably for the call, with the pc still pointing to
+    /// the instruction applying the procedure, so the function can't keep a reference to it.
+    /// Starting the machine within the function runs the controller again from the start.
+    /// `execute` fails with `ExecuteFailure` since there is no machine to pass.
+    pub fn with_machine<F, S>(name: S, num: usize, f: F) -> Self
+    where
+        F: Fn(&mut Machine, Vec<Value>) -> MResult<Value> + Send + Sync + 'static,
+        S: Into<String>,
+    {
+        let name = name.into();
+        let message = format!("{} requires a machine", name);
+        Self {
+            name,
+            proc: Arc::new(move |_| Err(ProcedureError::ExecuteFailure(message.clone()))?),
+            machine_proc: Some(Arc::new(f)),
+            min_arg_num: num,
+            max_arg_num: None,
         }
     }
 
@@ -33,16 +111,56 @@ impl Procedure {
         duplicate
     }
 
+    /// Constructs a procedure applying `f` to its arguments,
+    /// then `g` to the result as its sole argument. It takes the arguments of `f`.
+    pub fn compose<S: Into<String>>(f: &Self, g: &Self, name: S) -> Self {
+        let (f, g) = (f.clone(), g.clone());
+        Self {
+            name: name.into(),
+            min_arg_num: f.min_arg_num,
+            max_arg_num: f.max_arg_num,
+            machine_proc: None,
+            proc: Arc::new(move |args: Vec<Value>| g.execute(vec![f.execute(args)?])),
+        }
+    }
+
     /// Execute the inner function with parameters `args`
     pub fn execute(&self, args: Vec<Value>) -> MResult<Value> {
-        if args.len() < self.min_arg_num {
+        self.check_arg_num(args.len())?;
+        (self.proc)(args)
+    }
+
+    /// Executes like `execute`, passing `machine` to a procedure made by `with_machine`.
+    pub fn execute_with(&self, machine: &mut Machine, args: Vec<Value>) -> MResult<Value> {
+        match &self.machine_proc {
+            Some(f) => {
+                self.check_arg_num(args.len())?;
+                f(machine, args)
+            }
+            None => self.execute(args),
+        }
+    }
+
+    /// Whether the procedure receives the machine, i.e. it's made by `with_machine`.
+    pub fn needs_machine(&self) -> bool {
+        self.machine_proc.is_some()
+    }
+
+    fn check_arg_num(&self, got: usize) -> MResult<()> {
+        if got < self.min_arg_num {
             Err(ProcedureError::ArgsTooFew {
                 name: self.get_name(),
                 expected: self.min_arg_num,
-                got: args.len(),
+                got,
+            })?
+        } else if let Some(max) = self.max_arg_num.filter(|&max| got > max) {
+            Err(ProcedureError::ArgsTooMany {
+                name: self.get_name(),
+                max,
+                got,
             })?
         } else {
-            Ok((self.proc)(args))
+            Ok(())
         }
     }
 
@@ -53,11 +171,21 @@ impl Procedure {
     pub fn get_arg_num(&self) -> usize {
         self.min_arg_num
     }
+
+    /// The maximum number of arguments, `None` if unlimited.
+    pub fn get_max_arg_num(&self) -> Option<usize> {
+        self.max_arg_num
+    }
 }
 
+/// Two procedures are equal only if they share the same inner function,
+/// i.e. one is a clone of the other.
 impl PartialEq for Procedure {
     fn eq(&self, other: &Self) -> bool {
-        self.name == other.name && self.min_arg_num == other.min_arg_num
+        self.name == other.name
+            && self.min_arg_num == other.min_arg_num
+            && self.max_arg_num == other.max_arg_num
+            && Arc::ptr_eq(&self.proc, &other.proc)
     }
 }
 
@@ -66,7 +194,9 @@ impl Clone for Procedure {
         Self {
             name: self.get_name(),
             proc: self.proc.clone(),
+            machine_proc: self.machine_proc.clone(),
             min_arg_num: self.min_arg_num,
+            max_arg_num: self.max_arg_num,
         }
     }
 }
@@ -77,8 +207,27 @@ impl ToValue for Procedure {
     }
 }
 
+/// Makes a `Procedure` from a closure-like body with typed arguments,
+/// each converted from a `Value` by `TryFromValue`, panicking if the conversion fails.
+/// An argument typed as `Value` is passed as is.
+/// With a leading `fallible`, a failed conversion is returned as an error by `execute`.
+/// The forms with typed arguments take exactly `$num` arguments.
 #[macro_export]
 macro_rules! make_proc {
+    ( fallible $name:literal, $num:literal, |$($arg_name:ident:$arg_type:ty),+| $exps:expr ) => {
+        Procedure::fallible($name, $num, |args| {
+            let mut idx = 0usize;
+            $(
+                let $arg_name: $arg_type = $crate::machine::value::TryFromValue::try_from(&args[idx])?;
+                #[allow(unused_assignments)]
+                {
+                    idx += 1;
+                }
+            )+
+            Ok($exps)
+        })
+        .with_max_args($num)
+    };
     ( $name:literal, |_| { $exps:expr }) => {
         Procedure::new($name, 0, |_| $exps)
     };
@@ -94,6 +243,7 @@ macro_rules! make_proc {
             )+
             $exps
         })
+        .with_max_args($num)
     };
     ( $name:literal, |_| $exps:expr ) => {
         make_proc!($name, |_| { $exps })
@@ -106,6 +256,7 @@ macro_rules! make_proc {
 #[cfg(test)]
 mod procedure_tests {
     use super::*;
+    use crate::machine::{errors::MachineError, value::TryFromValue};
 
     #[test]
     fn test_procedure_constructor() {
@@ -123,6 +274,96 @@ mod procedure_tests {
         assert_eq!(Ok(3.to_value()), res);
     }
 
+    #[test]
+    fn test_try_new() {
+        let sqrt = Procedure::try_new("sqrt", 1, |args: Vec<Value>| {
+            let x = f64::try_from(&args[0])?;
+            if x < 0.0 {
+                Err(ProcedureError::ExecuteFailure(format!(
+                    "sqrt of negative {}",
+                    x
+                )))?
+            }
+            Ok(x.sqrt().to_value())
+        });
+        assert_eq!(Ok(Value::Num(3.0)), sqrt.execute(vec![9.to_value()]));
+        assert_eq!(
+            Err(ProcedureError::ExecuteFailure("sqrt of negative -4".into()).into()),
+            sqrt.execute(vec![(-4).to_value()])
+        );
+        assert!(matches!(
+            sqrt.execute(vec!["a".to_value()]),
+            Err(MachineError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_procedure_equality() {
+        let proc = Procedure::new("add", 2, |args: Vec<Value>| {
+            args[0].clone() + args[1].clone()
+        });
+        assert!(proc == proc.clone());
+        let other = Procedure::new("add", 2, |args: Vec<Value>| {
+            args[0].clone() - args[1].clone()
+        });
+        assert!(proc != other);
+        let same_body = Procedure::new("add", 2, |args: Vec<Value>| {
+            args[0].clone() + args[1].clone()
+        });
+        assert!(proc != same_body);
+        assert!(proc != Procedure::duplicate(&proc, "plus"));
+    }
+
+    #[test]
+    fn test_ranged_procedure() {
+        let proc = Procedure::new_ranged("pick", 1, 2, |args: Vec<Value>| args.len());
+        assert_eq!(Ok(1.to_value()), proc.execute(vec![Value::Nil]));
+        assert_eq!(Ok(2.to_value()), proc.execute(vec![Value::Nil, Value::Nil]));
+        assert_eq!(
+            Err(ProcedureError::ArgsTooFew {
+                name: "pick".into(),
+                expected: 1,
+                got: 0,
+            }
+            .into()),
+            proc.execute(vec![])
+        );
+        assert_eq!(
+            Err(ProcedureError::ArgsTooMany {
+                name: "pick".into(),
+                max: 2,
+                got: 3,
+            }
+            .into()),
+            proc.execute(vec![Value::Nil; 3])
+        );
+        let proc = make_proc!("not", 1, |arg: bool| !arg);
+        assert_eq!(Some(1), proc.get_max_arg_num());
+        assert!(proc
+            .execute(vec![true.to_value(), true.to_value()])
+            .is_err());
+        assert_eq!(None, make_proc!("any", |_| 0).get_max_arg_num());
+    }
+
+    #[test]
+    fn test_compose() {
+        let car = make_proc!(fallible "car", 1, |list: Vec<Value>| list[0].clone());
+        let cdr = make_proc!(fallible "cdr", 1, |list: Vec<Value>| list[1..].to_vec());
+        let cadr = Procedure::compose(&cdr, &car, "cadr");
+        assert_eq!("cadr", cadr.get_name());
+        assert_eq!(1, cadr.get_arg_num());
+        let list = Value::new(vec!["a", "b", "c"]);
+        assert_eq!(Ok(Value::new("b")), cadr.execute(vec![list]));
+        assert!(cadr.execute(vec![]).is_err());
+        let negate = make_proc!(fallible "negate", 1, |n: i32| -n);
+        let negate_car = Procedure::compose(&car, &negate, "negate-car");
+        assert_eq!(
+            Ok(Value::new(-1)),
+            negate_car.execute(vec![Value::new(vec![1])])
+        );
+        assert!(negate_car.execute(vec![Value::new(vec!["a"])]).is_err());
+    }
+
     #[test]
     fn test_procedure_macro() {
         let proc = make_proc!("test", |_| Value::Num(1.0));
@@ -132,4 +373,24 @@ mod procedure_tests {
         let res = proc.execute(vec![1.to_value(), 2.to_value()]);
         assert_eq!(Ok(3.to_value()), res);
     }
+
+    #[test]
+    fn test_fallible_procedure_macro() {
+        let proc = make_proc!(fallible "repeat", 2, |n: i32, raw: Value| {
+            vec![raw; n as usize]
+        });
+        assert_eq!(
+            Ok(Value::new(vec!["a", "a"])),
+            proc.execute(vec![2.to_value(), "a".to_value()])
+        );
+        assert!(matches!(
+            proc.execute(vec!["a".to_value(), 2.to_value()]),
+            Err(MachineError::TypeError(_))
+        ));
+        let proc = make_proc!("second", 2, |_first: i32, raw: Value| raw);
+        assert_eq!(
+            Ok(Value::new("a")),
+            proc.execute(vec![1.to_value(), "a".to_value()])
+        );
+    }
 }
@@ -0,0 +1,74 @@
+//! A promise structure for delayed evaluation.
+
+use std::sync::{Arc, Mutex};
+
+use super::errors::MResult;
+use super::procedure::Procedure;
+use super::value::{ToValue, Value};
+
+/// A delayed computation wrapping a procedure of zero arguments.
+/// The procedure runs the first time the promise is forced,
+/// later forces return the memoized result.
+#[derive(Clone)]
+pub struct Promise {
+    thunk: Procedure,
+    memo: Arc<Mutex<Option<Value>>>,
+}
+
+impl Promise {
+    pub fn new(thunk: Procedure) -> Self {
+        Self {
+            thunk,
+            memo: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn is_forced(&self) -> bool {
+        self.memo.lock().unwrap().is_some()
+    }
+
+    /// Evaluates the delayed computation, or returns the memoized result.
+    pub fn force(&self) -> MResult<Value> {
+        if let Some(value) = self.memo.lock().unwrap().as_ref() {
+            return Ok(value.clone());
+        }
+        // Don't hold the lock while running the thunk,
+        // which may force this promise again.
+        let value = self.thunk.execute(vec![])?;
+        Ok(self.memo.lock().unwrap().get_or_insert(value).clone())
+    }
+}
+
+impl PartialEq for Promise {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.memo, &other.memo)
+    }
+}
+
+impl ToValue for Promise {
+    fn to_value(self) -> Value {
+        Value::Promise(self)
+    }
+}
+
+#[cfg(test)]
+mod promise_tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_force_promise() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let calls = counter.clone();
+        let promise = Promise::new(Procedure::new("thunk", 0, move |_| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Value::Num(42.0)
+        }));
+        assert!(!promise.is_forced());
+        assert_eq!(Ok(Value::Num(42.0)), promise.force());
+        assert!(promise.is_forced());
+        assert_eq!(Ok(Value::Num(42.0)), promise.clone().force());
+        assert_eq!(1, counter.load(Ordering::SeqCst));
+    }
+}
@@ -2,12 +2,14 @@
 
 use super::value::{ToValue, Value};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Stack {
     stack: Vec<Value>,
     num_pushes: i32,
     max_depth: i32,
     curr_depth: i32,
+    /// The depth that a push may not exceed, unlimited if `None`.
+    max_depth_limit: Option<usize>,
 }
 
 impl Stack {
@@ -17,6 +19,7 @@ impl Stack {
             num_pushes: 0,
             max_depth: 0,
             curr_depth: 0,
+            max_depth_limit: None,
         }
     }
 
@@ -24,11 +27,24 @@ impl Stack {
         self.curr_depth == 0 && self.stack.is_empty()
     }
 
-    pub fn push<T: ToValue>(&mut self, item: T) {
+    /// Sets the depth that a push may not exceed, `None` for unlimited.
+    pub fn set_max_depth_limit(&mut self, limit: Option<usize>) {
+        self.max_depth_limit = limit;
+    }
+
+    pub fn max_depth_limit(&self) -> Option<usize> {
+        self.max_depth_limit
+    }
+
+    pub fn push<T: ToValue>(&mut self, item: T) -> Result<(), &'static str> {
+        if matches!(self.max_depth_limit, Some(limit) if self.stack.len() >= limit) {
+            return Err("try to push onto a full stack");
+        }
         self.stack.push(item.to_value());
         self.num_pushes += 1;
         self.curr_depth += 1;
         self.max_depth = std::cmp::max(self.curr_depth, self.max_depth);
+        Ok(())
     }
 
     pub fn pop(&mut self) -> Result<Value, &'static str> {
@@ -40,6 +56,16 @@ impl Stack {
         }
     }
 
+    /// Returns the top item without popping it.
+    pub fn peek(&self) -> Result<&Value, &'static str> {
+        self.stack.last().ok_or("try to peek an empty stack")
+    }
+
+    /// The items from the bottom to the top.
+    pub fn items(&self) -> &[Value] {
+        &self.stack
+    }
+
     pub fn initialize(&mut self) {
         self.stack.clear();
         self.num_pushes = 0;
@@ -47,6 +73,24 @@ impl Stack {
         self.curr_depth = 0;
     }
 
+    /// Restarts the statistics from the current contents, which are kept.
+    pub fn reset_statistics(&mut self) {
+        self.num_pushes = 0;
+        self.max_depth = self.curr_depth;
+    }
+
+    pub fn total_pushes(&self) -> i32 {
+        self.num_pushes
+    }
+
+    pub fn maximum_depth(&self) -> i32 {
+        self.max_depth
+    }
+
+    pub fn current_depth(&self) -> i32 {
+        self.curr_depth
+    }
+
     pub fn print_statistics(&self) {
         println!(
             "\ntotal-pushes = {} maximum-depth = {}",
@@ -63,7 +107,7 @@ mod stack_tests {
     fn test_push_item() {
         let mut stack: Stack = Stack::new();
         let right: i32 = 42;
-        stack.push(Value::new(right));
+        stack.push(Value::new(right)).unwrap();
         assert_eq!(stack.num_pushes, 1);
         assert_eq!(stack.curr_depth, 1);
         assert_eq!(stack.max_depth, 1);
@@ -73,7 +117,7 @@ mod stack_tests {
     fn test_pop_item() {
         let mut stack: Stack = Stack::new();
         let right = Value::new(42);
-        stack.push(right.clone());
+        stack.push(right.clone()).unwrap();
         assert_eq!(stack.num_pushes, 1);
         assert_eq!(stack.curr_depth, 1);
         assert_eq!(stack.max_depth, 1);
@@ -85,11 +129,36 @@ mod stack_tests {
         assert_eq!(stack.max_depth, 1);
     }
 
+    #[test]
+    fn test_peek_item() {
+        let mut stack: Stack = Stack::new();
+        assert!(stack.peek().is_err());
+        stack.push(Value::new(1)).unwrap();
+        stack.push(Value::new(2)).unwrap();
+        assert_eq!(Ok(&Value::new(2)), stack.peek());
+        assert_eq!(stack.curr_depth, 2);
+    }
+
+    #[test]
+    fn test_max_depth_limit() {
+        let mut stack: Stack = Stack::new();
+        stack.set_max_depth_limit(Some(2));
+        assert!(stack.push(Value::new(1)).is_ok());
+        assert!(stack.push(Value::new(2)).is_ok());
+        assert!(stack.push(Value::new(3)).is_err());
+        assert_eq!(stack.num_pushes, 2);
+        assert_eq!(stack.curr_depth, 2);
+        assert!(stack.pop().is_ok());
+        assert!(stack.push(Value::new(3)).is_ok());
+        stack.initialize();
+        assert_eq!(Some(2), stack.max_depth_limit());
+    }
+
     #[test]
     fn test_initialize() {
         let mut stack: Stack = Stack::new();
-        stack.push(Value::new("Hello!".to_string()));
-        stack.push(Value::new(42));
+        stack.push(Value::new("Hello!".to_string())).unwrap();
+        stack.push(Value::new(42)).unwrap();
         assert!(stack.pop().is_ok());
         stack.initialize();
         assert!(stack.is_empty());
@@ -103,8 +172,8 @@ mod stack_tests {
         let mut stack: Stack = Stack::new();
         assert!(stack.is_empty());
 
-        stack.push(Value::new("Hello!".to_string()));
-        stack.push(Value::new(42));
+        stack.push(Value::new("Hello!".to_string())).unwrap();
+        stack.push(Value::new(42)).unwrap();
         assert!(!stack.is_empty());
 
         stack.pop().ok();
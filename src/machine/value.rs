@@ -2,23 +2,55 @@ use std::{
     any::Any,
     cmp::Ordering,
     fmt,
+    iter::FromIterator,
     ops::{Add, Div, Mul, Neg, Sub},
+    sync::Arc,
+    time::Duration,
 };
 
-use super::errors::{MResult, ProcedureError, TypeError};
+use super::errors::{MResult, MachineError, ProcedureError, TypeError};
 use super::procedure::Procedure;
+use super::promise::Promise;
+use crate::parser::{char_literal, escape_string};
 
 /// An enum of the possible value types that can be sent to an operation.
-#[derive(Clone, PartialEq)]
+///
+/// An `Integer` is exact, and a `Num` is a float point number.
+/// Integers and numbers holding the same number are equal.
+/// A `Cons` is a pair sharing its parts, see `Value::cons`,
+/// and a chain of them ending in an empty list equals the `List` of their heads.
+#[derive(Clone)]
 pub enum Value {
+    Integer(i64),
     Num(f64),
     Symbol(String),
     String(String),
     Boolean(bool),
+    Char(char),
     List(Vec<Value>),
+    Cons(Arc<Pair>),
     Nil,
     Pointer(usize),
     Procedure(Procedure),
+    Promise(Promise),
+}
+
+/// The head and the rest of a `Value::Cons`.
+pub struct Pair(pub Value, pub Value);
+
+impl Drop for Pair {
+    /// Unlinks the rest of the chain one pair at a time,
+    /// so dropping a long chain doesn't recurse once per pair.
+    fn drop(&mut self) {
+        let mut rest = std::mem::replace(&mut self.1, Value::Nil);
+        while let Value::Cons(cell) = rest {
+            match Arc::try_unwrap(cell) {
+                Ok(mut pair) => rest = std::mem::replace(&mut pair.1, Value::Nil),
+                // The rest is shared, and stays alive.
+                Err(_) => break,
+            }
+        }
+    }
 }
 
 impl Value {
@@ -27,11 +59,11 @@ impl Value {
     }
 
     pub fn zero() -> Self {
-        Value::Num(0.0)
+        Value::Integer(0)
     }
 
     pub fn one() -> Self {
-        Value::Num(1.0)
+        Value::Integer(1)
     }
 
     pub fn nil() -> Self {
@@ -42,6 +74,22 @@ impl Value {
         Value::List(vec![])
     }
 
+    /// Makes a symbol named `name`.
+    ///
+    /// Leading and trailing whitespace are trimmed. A symbol can't be empty,
+    /// so an empty or whitespace-only `name` gives `Value::Nil` instead.
+    pub fn symbol<S: Into<String>>(name: S) -> Self {
+        let name = name.into();
+        let trimmed = name.trim();
+        if trimmed.is_empty() {
+            Value::Nil
+        } else if trimmed.len() == name.len() {
+            Value::Symbol(name)
+        } else {
+            Value::Symbol(trimmed.to_string())
+        }
+    }
+
     pub fn perform(&self, args: Vec<Value>) -> MResult<Self> {
         if let Self::Procedure(p) = self {
             p.execute(args)
@@ -50,11 +98,25 @@ impl Value {
         }
     }
 
-    pub fn eq_num<F: Into<f64>>(&self, num: F) -> bool {
-        if let Self::Num(f) = self {
-            f.eq(&num.into())
+    /// Forces a promise, other values are returned as they are.
+    pub fn force(&self) -> MResult<Self> {
+        if let Self::Promise(p) = self {
+            p.force()
         } else {
-            false
+            Ok(self.clone())
+        }
+    }
+
+    pub fn eq_num<F: Into<f64>>(&self, num: F) -> bool {
+        self.to_f64().is_some_and(|f| f.eq(&num.into()))
+    }
+
+    /// Returns the number held by an `Integer` or a `Num`, as an `f64`.
+    pub fn to_f64(&self) -> Option<f64> {
+        match self {
+            Self::Integer(i) => Some(*i as f64),
+            Self::Num(f) => Some(*f),
+            _ => None,
         }
     }
 
@@ -66,44 +128,42 @@ impl Value {
         }
     }
 
+    /// Checks whether the value is a number, either an `Integer` or a `Num`.
     pub fn is_num(&self) -> bool {
-        if let Self::Num(_) = self {
-            true
-        } else {
-            false
+        matches!(self, Self::Integer(_) | Self::Num(_))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Self::Integer(_))
+    }
+
+    /// Whether the value is an integer, or a float point number without a fractional part.
+    pub fn is_integral(&self) -> bool {
+        match self {
+            Self::Integer(_) => true,
+            Self::Num(n) => n.is_finite() && n.fract() == 0.0,
+            _ => false,
         }
     }
 
     pub fn is_symbol(&self) -> bool {
-        if let Self::Symbol(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::Symbol(_))
     }
 
     pub fn is_string(&self) -> bool {
-        if let Self::String(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::String(_))
     }
 
     pub fn is_pointer(&self) -> bool {
-        if let Self::Pointer(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::Pointer(_))
     }
 
     pub fn is_bool(&self) -> bool {
-        if let Self::Boolean(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::Boolean(_))
+    }
+
+    pub fn is_char(&self) -> bool {
+        matches!(self, Self::Char(_))
     }
 
     pub fn is_true(&self) -> bool {
@@ -119,11 +179,7 @@ impl Value {
     }
 
     pub fn is_list(&self) -> bool {
-        if let Self::List(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::List(_))
     }
 
     pub fn is_empty_list(&self) -> bool {
@@ -135,10 +191,61 @@ impl Value {
     }
 
     pub fn is_procedure(&self) -> bool {
-        if let Self::Procedure(_) = self {
-            true
-        } else {
-            false
+        matches!(self, Self::Procedure(_))
+    }
+
+    pub fn is_promise(&self) -> bool {
+        matches!(self, Self::Promise(_))
+    }
+
+    /// Converts to an `i32` like `i32::try_from`, but also maps `#t` to 1 and `#f` to 0.
+    /// `i32::try_from` stays strict, and rejects booleans.
+    pub fn as_int_loose(&self) -> Result<i32, TypeError> {
+        match self {
+            Value::Boolean(b) => Ok(*b as i32),
+            other => i32::try_from(other),
+        }
+    }
+
+    /// Returns the canonical external representation of the value:
+    /// booleans as `#t`/`#f`, strings quoted and escaped,
+    /// and lists parenthesized. Procedures, promises and pointers have no
+    /// external representation, and are written as `#<...>`.
+    pub fn to_scheme_string(&self) -> String {
+        match self {
+            Value::Boolean(true) => "#t".into(),
+            Value::Boolean(false) => "#f".into(),
+            Value::Char(c) => char_literal(*c),
+            Value::Integer(v) => v.to_string(),
+            Value::Num(v) if v.is_nan() => "+nan.0".into(),
+            Value::Num(v) if v.is_infinite() => if *v > 0.0 { "+inf.0" } else { "-inf.0" }.into(),
+            Value::Num(v) => v.to_string(),
+            Value::Symbol(v) => v.to_string(),
+            Value::String(v) => format!("\"{}\"", escape_string(v)),
+            Value::List(l) => format!(
+                "({})",
+                l.iter()
+                    .filter(|v| !v.is_nil())
+                    .map(Value::to_scheme_string)
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
+            Value::Cons(_) => match self.cons_parts() {
+                (items, None) => Value::List(items).to_scheme_string(),
+                (items, Some(tail)) => format!(
+                    "({} . {})",
+                    items
+                        .iter()
+                        .map(Value::to_scheme_string)
+                        .collect::<Vec<String>>()
+                        .join(" "),
+                    tail.to_scheme_string()
+                ),
+            },
+            Value::Nil => "()".into(),
+            Value::Procedure(p) => format!("#<procedure {}>", p.get_name()),
+            Value::Promise(_) => "#<promise>".into(),
+            Value::Pointer(v) => format!("#<pointer {}>", v),
         }
     }
 }
@@ -147,11 +254,15 @@ impl fmt::Debug for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Value::Boolean(v) => write!(f, "<Boolean {}>", v),
+            Value::Char(c) => write!(f, "<Char {}>", char_literal(*c)),
+            Value::Integer(v) => write!(f, "<Integer {}>", v),
             Value::Num(v) => write!(f, "<Num {}>", v),
             Value::List(v) => write!(f, "<List {:?}>", v.type_id()),
+            Value::Cons(_) => write!(f, "<Cons {}>", self),
             Value::Symbol(v) => write!(f, "<Symbol {}>", v),
             Value::String(v) => write!(f, r#"<String "{}">"#, v),
             Value::Procedure(v) => write!(f, "<Procedure {}>", v.get_name()),
+            Value::Promise(v) => write!(f, "<Promise forced: {}>", v.is_forced()),
             Value::Pointer(v) => write!(f, "<Pointer {}>", v),
             Value::Nil => write!(f, "<Nil>"),
         }
@@ -162,27 +273,154 @@ impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Value::Boolean(v) => write!(f, "{}", if *v { "#t" } else { "#f" }),
+            Value::Char(c) => write!(f, "{}", char_literal(*c)),
+            Value::Integer(v) => write!(f, "{}", v),
             Value::Num(v) => write!(f, "{}", v),
             Value::Symbol(v) => write!(f, "{}", v),
             Value::List(l) => write!(f, "{}", values_to_str(l)),
+            Value::Cons(_) => match self.cons_parts() {
+                (items, None) => write!(f, "{}", values_to_str(&items)),
+                (items, Some(tail)) => {
+                    let items = values_to_str(&items);
+                    write!(f, "{} . {})", &items[..items.len() - 1], tail)
+                }
+            },
             Value::String(v) => write!(f, r#""{}""#, v),
             Value::Procedure(p) => write!(f, "Procedure-{}", p.get_name()),
+            Value::Promise(_) => write!(f, "Promise"),
             Value::Pointer(v) => write!(f, "Pointer-{}", v),
             Value::Nil => write!(f, ""),
         }
     }
 }
 
+/// Applies an arithmetic operation, exactly on two integers if it doesn't overflow,
+/// otherwise on the numbers as `f64`. `None` if either value isn't a number.
+fn arithmetic(
+    lhs: &Value,
+    rhs: &Value,
+    exact: fn(i64, i64) -> Option<i64>,
+    inexact: fn(f64, f64) -> f64,
+) -> Option<Value> {
+    if let (Value::Integer(l), Value::Integer(r)) = (lhs, rhs) {
+        if let Some(result) = exact(*l, *r) {
+            return Some(Value::Integer(result));
+        }
+    }
+    Some(Value::Num(inexact(lhs.to_f64()?, rhs.to_f64()?)))
+}
+
+/// How `Value::add_in`, `sub_in` and `mul_in` handle two integers whose result
+/// is out of the range of `i64`, see `Machine::set_integer_overflow_mode`.
+/// Without a mode, the result is promoted to a `Num`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegerOverflowMode {
+    /// Fails with `IntegerOverflow`.
+    Checked,
+    /// Wraps around, like fixed-width hardware.
+    Wrapping,
+    /// Clamps to `i64::MIN` or `i64::MAX`.
+    Saturating,
+}
+
+/// The integer functions of an arithmetic operation, one per overflow mode.
+struct IntegerOps {
+    name: &'static str,
+    checked: fn(i64, i64) -> Option<i64>,
+    wrapping: fn(i64, i64) -> i64,
+    saturating: fn(i64, i64) -> i64,
+}
+
+/// Applies an arithmetic operation like `arithmetic`, but handles an overflow
+/// of two integers by `mode`, and fails if either value isn't a number.
+fn arithmetic_in(
+    lhs: &Value,
+    rhs: &Value,
+    mode: IntegerOverflowMode,
+    ops: IntegerOps,
+    inexact: fn(f64, f64) -> f64,
+) -> MResult<Value> {
+    match (lhs, rhs) {
+        (Value::Integer(l), Value::Integer(r)) => match mode {
+            IntegerOverflowMode::Checked => (ops.checked)(*l, *r)
+                .map(Value::Integer)
+                .ok_or_else(|| MachineError::IntegerOverflow(format!("{} {} {}", l, ops.name, r))),
+            IntegerOverflowMode::Wrapping => Ok(Value::Integer((ops.wrapping)(*l, *r))),
+            IntegerOverflowMode::Saturating => Ok(Value::Integer((ops.saturating)(*l, *r))),
+        },
+        _ => match (lhs.to_f64(), rhs.to_f64()) {
+            (Some(l), Some(r)) => Ok(Value::Num(inexact(l, r))),
+            (None, _) => Err(TypeError::expected("Value::Num").got(lhs.to_string()))?,
+            (_, None) => Err(TypeError::expected("Value::Num").got(rhs.to_string()))?,
+        },
+    }
+}
+
+impl Value {
+    /// Adds like `+`, handling an integer overflow by `mode`.
+    pub fn add_in(&self, rhs: &Value, mode: IntegerOverflowMode) -> MResult<Value> {
+        let ops = IntegerOps {
+            name: "+",
+            checked: i64::checked_add,
+            wrapping: i64::wrapping_add,
+            saturating: i64::saturating_add,
+        };
+        arithmetic_in(self, rhs, mode, ops, |l, r| l + r)
+    }
+
+    /// Subtracts like `-`, handling an integer overflow by `mode`.
+    pub fn sub_in(&self, rhs: &Value, mode: IntegerOverflowMode) -> MResult<Value> {
+        let ops = IntegerOps {
+            name: "-",
+            checked: i64::checked_sub,
+            wrapping: i64::wrapping_sub,
+            saturating: i64::saturating_sub,
+        };
+        arithmetic_in(self, rhs, mode, ops, |l, r| l - r)
+    }
+
+    /// Multiplies like `*`, handling an integer overflow by `mode`.
+    pub fn mul_in(&self, rhs: &Value, mode: IntegerOverflowMode) -> MResult<Value> {
+        let ops = IntegerOps {
+            name: "*",
+            checked: i64::checked_mul,
+            wrapping: i64::wrapping_mul,
+            saturating: i64::saturating_mul,
+        };
+        arithmetic_in(self, rhs, mode, ops, |l, r| l * r)
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Integer(l), Self::Integer(r)) => l == r,
+            (Self::Integer(_), Self::Num(_)) | (Self::Num(_), Self::Integer(_)) => {
+                self.to_f64() == other.to_f64()
+            }
+            (Self::Num(l), Self::Num(r)) => l == r,
+            (Self::Symbol(l), Self::Symbol(r)) | (Self::String(l), Self::String(r)) => l == r,
+            (Self::Boolean(l), Self::Boolean(r)) => l == r,
+            (Self::Char(l), Self::Char(r)) => l == r,
+            (Self::List(l), Self::List(r)) => l == r,
+            (Self::Cons(l), Self::Cons(r)) if Arc::ptr_eq(l, r) => true,
+            (Self::Cons(_), _) | (_, Self::Cons(_)) => self.cons_parts() == other.cons_parts(),
+            (Self::Nil, Self::Nil) => true,
+            (Self::Pointer(l), Self::Pointer(r)) => l == r,
+            (Self::Procedure(l), Self::Procedure(r)) => l == r,
+            (Self::Promise(l), Self::Promise(r)) => l == r,
+            _ => false,
+        }
+    }
+}
+
 impl Add for Value {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Value::Num(augend), Value::Num(addend)) => Value::Num(augend + addend),
-            (augend, addend) => panic!(
-                "Unable to perform addition between {} and {}.",
-                augend, addend
-            ),
+        match arithmetic(&self, &rhs, i64::checked_add, |l, r| l + r) {
+            Some(sum) => sum,
+            None => panic!("Unable to perform addition between {} and {}.", self, rhs),
         }
     }
 }
@@ -191,12 +429,9 @@ impl Sub for Value {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Value::Num(minuend), Value::Num(subtrahend)) => Value::Num(minuend - subtrahend),
-            (minuend, subtrahend) => panic!(
-                "Unable to perform subtraction between {} and {}",
-                minuend, subtrahend
-            ),
+        match arithmetic(&self, &rhs, i64::checked_sub, |l, r| l - r) {
+            Some(difference) => difference,
+            None => panic!("Unable to perform subtraction between {} and {}", self, rhs),
         }
     }
 }
@@ -205,10 +440,12 @@ impl Neg for Value {
     type Output = Self;
 
     fn neg(self) -> Self::Output {
-        if let Self::Num(n) = self {
-            Self::Num(-n)
-        } else {
-            panic!("Unable to perform negation with {}", self);
+        match self {
+            Self::Integer(i) => i
+                .checked_neg()
+                .map_or(Self::Num(-(i as f64)), Self::Integer),
+            Self::Num(n) => Self::Num(-n),
+            _ => panic!("Unable to perform negation with {}", self),
         }
     }
 }
@@ -217,13 +454,11 @@ impl Mul for Value {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Value::Num(multiplier), Value::Num(multiplicand)) => {
-                Value::Num(multiplier * multiplicand)
-            }
-            (multiplier, multiplicand) => panic!(
+        match arithmetic(&self, &rhs, i64::checked_mul, |l, r| l * r) {
+            Some(product) => product,
+            None => panic!(
                 "Unable to perform multiplication between {} and {}",
-                multiplier, multiplicand
+                self, rhs
             ),
         }
     }
@@ -236,12 +471,31 @@ impl Div for Value {
         if rhs.eq_num(0) {
             panic!("Cannot divide by zero-valued `Value::Num`!")
         }
-        match (self, rhs) {
-            (Value::Num(dividend), Value::Num(divisor)) => Value::Num(dividend / divisor),
-            (dividend, divisor) => panic!(
-                "Unable to perform division between {} and {}",
-                dividend, divisor
-            ),
+        // The quotient of two integers is an integer only if the division is exact.
+        let exact = |l: i64, r: i64| match l.checked_rem(r) {
+            Some(0) => l.checked_div(r),
+            _ => None,
+        };
+        match arithmetic(&self, &rhs, exact, |l, r| l / r) {
+            Some(quotient) => quotient,
+            None => panic!("Unable to perform division between {} and {}", self, rhs),
+        }
+    }
+}
+
+impl Value {
+    /// Divides the value by `rhs` like `/`, failing instead of panicking
+    /// if either isn't a number or `rhs` is zero.
+    pub fn checked_div(&self, rhs: &Value) -> MResult<Value> {
+        for value in [self, rhs].iter() {
+            if !value.is_num() {
+                Err(TypeError::expected("Value::Num").got(value.to_string()))?;
+            }
+        }
+        if rhs.eq_num(0) {
+            Err(MachineError::DivisionByZero(self.to_string()))
+        } else {
+            Ok(self.clone() / rhs.clone())
         }
     }
 }
@@ -249,13 +503,36 @@ impl Div for Value {
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         match (self, other) {
+            (Self::Integer(l), Self::Integer(r)) => l.partial_cmp(r),
+            (Self::Integer(_), Self::Num(_)) | (Self::Num(_), Self::Integer(_)) => {
+                self.to_f64()?.partial_cmp(&other.to_f64()?)
+            }
             (Self::Num(l), Self::Num(r)) => l.partial_cmp(r),
+            (Self::Pointer(l), Self::Pointer(r)) => l.partial_cmp(r),
+            (Self::Char(l), Self::Char(r)) => l.partial_cmp(r),
+            (Self::Symbol(l), Self::Symbol(r)) | (Self::String(l), Self::String(r)) => {
+                l.partial_cmp(r)
+            }
+            // A chain of pairs is compared like the list of its heads.
+            (Self::Cons(_), _) | (_, Self::Cons(_)) => {
+                self.to_list().ok()?.partial_cmp(&other.to_list().ok()?)
+            }
+            // Lists are compared element-wise, and only if all the elements are comparable.
+            (Self::List(l), Self::List(r)) => {
+                let orderings = l
+                    .iter()
+                    .zip(r.iter())
+                    .map(|(a, b)| a.partial_cmp(b))
+                    .collect::<Option<Vec<Ordering>>>()?;
+                let ordering = orderings.into_iter().find(|o| *o != Ordering::Equal);
+                Some(ordering.unwrap_or_else(|| l.len().cmp(&r.len())))
+            }
             _ => None,
         }
     }
 }
 
-pub fn values_to_str(vals: &Vec<Value>) -> String {
+pub fn values_to_str(vals: &[Value]) -> String {
     format!(
         "({})",
         vals.iter()
@@ -281,7 +558,14 @@ pub trait NonValue: ToValue {}
 impl NonValue for i32 {}
 impl ToValue for i32 {
     fn to_value(self) -> Value {
-        Value::Num(self as f64)
+        Value::Integer(self as i64)
+    }
+}
+
+impl NonValue for i64 {}
+impl ToValue for i64 {
+    fn to_value(self) -> Value {
+        Value::Integer(self)
     }
 }
 
@@ -295,14 +579,30 @@ impl ToValue for f64 {
 impl NonValue for u64 {}
 impl ToValue for u64 {
     fn to_value(self) -> Value {
-        Value::Num(self as f64)
+        if self <= i64::MAX as Self {
+            Value::Integer(self as i64)
+        } else {
+            Value::Num(self as f64)
+        }
     }
 }
 
 impl NonValue for usize {}
 impl ToValue for usize {
     fn to_value(self) -> Value {
-        Value::Num(self as f64)
+        if self <= i64::MAX as Self {
+            Value::Integer(self as i64)
+        } else {
+            Value::Num(self as f64)
+        }
+    }
+}
+
+/// A duration is a `Value::Num` of milliseconds.
+impl NonValue for Duration {}
+impl ToValue for Duration {
+    fn to_value(self) -> Value {
+        Value::Num(self.as_secs_f64() * 1000.0)
     }
 }
 
@@ -319,7 +619,7 @@ impl ToValue for String {
         if self.starts_with('"') {
             Value::String(self)
         } else {
-            Value::Symbol(self)
+            Value::symbol(self)
         }
     }
 }
@@ -331,7 +631,7 @@ impl ToValue for &dyn ToString {
         if string.starts_with('"') {
             Value::String(string)
         } else {
-            Value::Symbol(string)
+            Value::symbol(string)
         }
     }
 }
@@ -343,7 +643,7 @@ impl ToValue for &'static str {
         if string.starts_with('"') {
             Value::String(string)
         } else {
-            Value::Symbol(string)
+            Value::symbol(string)
         }
     }
 }
@@ -359,6 +659,31 @@ impl<T: ToValue> ToValue for Vec<T> {
     }
 }
 
+/// An error is tagged as the list `(error "<message>")`, to be inspected by the controller.
+impl<T: ToValue, E: fmt::Display> NonValue for Result<T, E> {}
+impl<T: ToValue, E: fmt::Display> ToValue for Result<T, E> {
+    fn to_value(self) -> Value {
+        match self {
+            Ok(v) => v.to_value(),
+            Err(e) => Value::List(vec![Value::symbol("error"), Value::String(e.to_string())]),
+        }
+    }
+}
+
+/// Builds a `Value::List` directly from the items of an iterator.
+impl<T: ToValue> FromIterator<T> for Value {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Value::List(iter.into_iter().map(|v| v.to_value()).collect())
+    }
+}
+
+impl NonValue for char {}
+impl ToValue for char {
+    fn to_value(self) -> Value {
+        Value::Char(self)
+    }
+}
+
 impl ToValue for () {
     fn to_value(self) -> Value {
         Value::Nil
@@ -379,6 +704,8 @@ impl TryFromValue for i32 {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         let expected = TypeError::expected("Value::Num");
         match v {
+            Value::Integer(val) => std::convert::TryFrom::try_from(*val)
+                .map_err(|_| expected.got(format!("Integer {} out of range", val))),
             Value::Num(val) => Ok(*val as i32),
             Value::Symbol(val) => val
                 .parse::<i32>()
@@ -392,6 +719,7 @@ impl TryFromValue for f64 {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         let expected = TypeError::expected("Value::Num");
         match v {
+            Value::Integer(val) => Ok(*val as f64),
             Value::Num(val) => Ok(*val),
             Value::Symbol(val) => val
                 .parse::<f64>()
@@ -405,6 +733,8 @@ impl TryFromValue for u64 {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         let expected = TypeError::expected("Value::Num");
         match v {
+            Value::Integer(val) => std::convert::TryFrom::try_from(*val)
+                .map_err(|_| expected.got(format!("Integer {} out of range", val))),
             Value::Num(val) => Ok(*val as u64),
             Value::Symbol(val) => val
                 .parse::<u64>()
@@ -418,6 +748,8 @@ impl TryFromValue for usize {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         let expected = TypeError::expected("Value::Num");
         match v {
+            Value::Integer(val) => std::convert::TryFrom::try_from(*val)
+                .map_err(|_| expected.got(format!("Integer {} out of range", val))),
             Value::Num(val) => Ok(*val as usize),
             Value::Pointer(val) => Ok(*val),
             Value::Symbol(val) => val
@@ -447,10 +779,19 @@ impl TryFromValue for bool {
     }
 }
 
+impl TryFromValue for char {
+    fn try_from(v: &Value) -> Result<Self, TypeError> {
+        match v {
+            Value::Char(c) => Ok(*c),
+            _ => Err(TypeError::expected("Value::Char").got(v.to_string())),
+        }
+    }
+}
+
 impl TryFromValue for String {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         match v {
-            Value::List(_) => Ok(format!("({})", v.to_string())),
+            Value::List(_) => Ok(format!("({})", v)),
             _ => Ok(v.to_string()),
         }
     }
@@ -460,6 +801,10 @@ impl TryFromValue for Vec<Value> {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         match v {
             Value::List(val) => Ok(val.clone()),
+            Value::Cons(_) => match v.cons_parts() {
+                (items, None) => Ok(items),
+                _ => Err(TypeError::expected("a proper list").got(v.to_string())),
+            },
             Value::Nil => Ok(vec![]),
             _ => Ok(vec![v.clone()]),
         }
@@ -469,7 +814,8 @@ impl TryFromValue for Vec<Value> {
 impl TryFromValue for Vec<i32> {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         match v {
-            Value::List(val) => val.iter().map(|v| i32::try_from(v)).collect(),
+            Value::List(val) => val.iter().map(i32::try_from).collect(),
+            Value::Integer(n) => Ok(vec![*n as i32]),
             Value::Num(n) => Ok(vec![*n as i32]),
             _ => Err(TypeError::expected("Value::List | Value::Num").got(v.to_string())),
         }
@@ -479,7 +825,8 @@ impl TryFromValue for Vec<i32> {
 impl TryFromValue for Vec<f64> {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         match v {
-            Value::List(val) => val.iter().map(|v| f64::try_from(v)).collect(),
+            Value::List(val) => val.iter().map(f64::try_from).collect(),
+            Value::Integer(n) => Ok(vec![*n as f64]),
             Value::Num(n) => Ok(vec![*n]),
             _ => Err(TypeError::expected("Value::List | Value::Num").got(v.to_string())),
         }
@@ -489,7 +836,8 @@ impl TryFromValue for Vec<f64> {
 impl TryFromValue for Vec<u64> {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         match v {
-            Value::List(val) => val.iter().map(|v| u64::try_from(v)).collect(),
+            Value::List(val) => val.iter().map(u64::try_from).collect(),
+            Value::Integer(n) => Ok(vec![*n as u64]),
             Value::Num(n) => Ok(vec![*n as u64]),
             _ => Err(TypeError::expected("Value::List | Value::Num").got(v.to_string())),
         }
@@ -499,7 +847,8 @@ impl TryFromValue for Vec<u64> {
 impl TryFromValue for Vec<usize> {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         match v {
-            Value::List(val) => val.iter().map(|v| usize::try_from(v)).collect(),
+            Value::List(val) => val.iter().map(usize::try_from).collect(),
+            Value::Integer(n) => Ok(vec![*n as usize]),
             Value::Num(n) => Ok(vec![*n as usize]),
             _ => Err(TypeError::expected("Value::List | Value::Num").got(v.to_string())),
         }
@@ -509,7 +858,7 @@ impl TryFromValue for Vec<usize> {
 impl TryFromValue for Vec<String> {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         match v {
-            Value::List(val) => val.iter().map(|v| String::try_from(v)).collect(),
+            Value::List(val) => val.iter().map(String::try_from).collect(),
             Value::String(s) | Value::Symbol(s) => Ok(vec![s.to_string()]),
             _ => Err(
                 TypeError::expected("Value::List | Value::String | Value::Symbol")
@@ -519,6 +868,25 @@ impl TryFromValue for Vec<String> {
     }
 }
 
+impl TryFromValue for Duration {
+    fn try_from(v: &Value) -> Result<Self, TypeError> {
+        match v.to_f64() {
+            Some(ms) if ms >= 0.0 && ms.is_finite() => Ok(Duration::from_secs_f64(ms / 1000.0)),
+            _ => Err(TypeError::expected("Value::Num of milliseconds").got(v.to_string())),
+        }
+    }
+}
+
+impl TryFromValue for Procedure {
+    fn try_from(v: &Value) -> Result<Self, TypeError> {
+        if let Value::Procedure(p) = v {
+            Ok(p.clone())
+        } else {
+            Err(TypeError::expected("Value::Procedure").got(v.to_string()))
+        }
+    }
+}
+
 impl TryFromValue for () {
     fn try_from(v: &Value) -> Result<Self, TypeError> {
         if v.is_nil() {
@@ -529,6 +897,42 @@ impl TryFromValue for () {
     }
 }
 
+/// Conversion from a `Value::Symbol`, for types whose values are named by symbols,
+/// like an enum with symbol-named variants.
+///
+/// Implement `from_symbol`, and then `TryFromValue` with `impl_try_from_symbol!`.
+pub trait FromSymbol: Sized {
+    /// Returns `None` if `symbol` doesn't name a value of this type.
+    fn from_symbol(symbol: &str) -> Option<Self>;
+
+    fn try_from_symbol(v: &Value) -> Result<Self, TypeError> {
+        let expected = TypeError::expected(format!(
+            "Value::Symbol naming a {}",
+            std::any::type_name::<Self>()
+        ));
+        match v {
+            Value::Symbol(s) => {
+                Self::from_symbol(s).ok_or_else(|| expected.got(format!("Symbol {}", s)))
+            }
+            _ => Err(expected.got(v.to_string())),
+        }
+    }
+}
+
+/// Implements `TryFromValue` for a type implementing `FromSymbol`.
+#[macro_export]
+macro_rules! impl_try_from_symbol {
+    ( $type:ty ) => {
+        impl $crate::machine::value::TryFromValue for $type {
+            fn try_from(
+                v: &$crate::machine::value::Value,
+            ) -> Result<Self, $crate::machine::errors::TypeError> {
+                <$type as $crate::machine::value::FromSymbol>::try_from_symbol(v)
+            }
+        }
+    };
+}
+
 #[cfg(test)]
 mod value_mod_tests {
     use super::*;
@@ -554,12 +958,117 @@ mod value_mod_tests {
         assert_eq!(Value::Nil, Value::new(()));
     }
 
+    #[test]
+    fn test_char() {
+        let c = 'a'.to_value();
+        assert_eq!(Value::Char('a'), c);
+        assert!(c.is_char());
+        assert_eq!(Ok('a'), char::try_from(&c));
+        assert!(char::try_from(&Value::new("a")).is_err());
+        assert_eq!("#\\a", c.to_string());
+        assert_eq!("<Char #\\space>", format!("{:?}", ' '.to_value()));
+        assert_eq!("#\\newline", '\n'.to_value().to_scheme_string());
+        assert_ne!(Value::Char('a'), Value::Symbol("a".into()));
+        assert!(Value::Char('a') < Value::Char('b'));
+    }
+
+    #[test]
+    fn test_result_to_value() {
+        assert_eq!(Value::Integer(1), Ok::<i32, String>(1).to_value());
+        let error = "1.5".parse::<i32>().to_value();
+        match &error {
+            Value::List(items) => {
+                assert_eq!(2, items.len());
+                assert_eq!(Value::Symbol("error".into()), items[0]);
+                assert_eq!(
+                    Value::String("invalid digit found in string".into()),
+                    items[1]
+                );
+            }
+            other => panic!("unexpected value: {:?}", other),
+        }
+        assert_eq!(
+            "(error \"invalid digit found in string\")",
+            error.to_scheme_string()
+        );
+    }
+
+    #[test]
+    fn test_from_iter() {
+        assert_eq!(Value::new(vec![1, 2, 3]), Value::from_iter(1..4));
+        assert_eq!(
+            Value::new(vec![true, false]),
+            vec![true, false].into_iter().collect()
+        );
+        assert_eq!(Value::List(vec![]), Value::from_iter(Vec::<i32>::new()));
+    }
+
+    #[test]
+    fn test_to_scheme_string() {
+        assert_eq!("#t", Value::Boolean(true).to_scheme_string());
+        assert_eq!("#f", Value::Boolean(false).to_scheme_string());
+        assert_eq!("1", Value::Num(1.0).to_scheme_string());
+        assert_eq!("-2.5", Value::Num(-2.5).to_scheme_string());
+        assert_eq!("+inf.0", Value::Num(f64::INFINITY).to_scheme_string());
+        assert_eq!("+nan.0", Value::Num(f64::NAN).to_scheme_string());
+        assert_eq!("abc", Value::Symbol("abc".into()).to_scheme_string());
+        assert_eq!(
+            r#""say \"hi\" \\o/""#,
+            Value::String(r#"say "hi" \o/"#.into()).to_scheme_string()
+        );
+        assert_eq!("()", Value::Nil.to_scheme_string());
+        assert_eq!("()", Value::empty_list().to_scheme_string());
+        assert_eq!(
+            "#<procedure car>",
+            Value::Procedure(Procedure::new("car", 1, |_| ())).to_scheme_string()
+        );
+        assert_eq!("#<pointer 3>", Value::Pointer(3).to_scheme_string());
+        let list = Value::List(vec![
+            Value::Num(1.0),
+            Value::Num(2.5),
+            Value::Symbol("abc".into()),
+            Value::String("a string".into()),
+            Value::List(vec![Value::Symbol("nested".into()), Value::empty_list()]),
+            Value::Boolean(false),
+        ]);
+        let text = list.to_scheme_string();
+        assert_eq!(r#"(1 2.5 abc "a string" (nested ()) #f)"#, text);
+        let (_, parsed) = crate::parser::rml_value(&text).unwrap();
+        assert_eq!(list, crate::rmlvalue_to_value(&parsed));
+    }
+
+    #[test]
+    fn test_symbol_normalization() {
+        assert_eq!(Value::Symbol("test".into()), Value::symbol("test"));
+        assert_eq!(Value::Symbol("test".into()), Value::symbol("  test\t"));
+        assert_eq!(Value::Nil, Value::symbol(""));
+        assert_eq!(Value::Nil, Value::symbol(" \t\n "));
+        assert_eq!(Value::Nil, Value::new(""));
+        assert_eq!(Value::Nil, Value::new(String::from("   ")));
+    }
+
     #[test]
     fn test_try_from_value() {
         assert_eq!(Ok(1), i32::try_from(&Value::new(1)));
         assert_eq!(Ok(1.0), f64::try_from(&Value::new(1.0)));
         assert_eq!(Ok(2), u64::try_from(&Value::new(2u64)));
         assert_eq!(Ok(3), usize::try_from(&Value::new(3usize)));
+        assert_eq!(
+            Err(TypeError::expected("Value::Num").got("Integer -1 out of range")),
+            usize::try_from(&Value::Integer(-1))
+        );
+        assert_eq!(
+            Err(TypeError::expected("Value::Num").got("Integer -2 out of range")),
+            u64::try_from(&Value::Integer(-2))
+        );
+        assert_eq!(
+            Err(TypeError::expected("Value::Num").got(format!("Integer {} out of range", i64::MAX))),
+            i32::try_from(&Value::Integer(i64::MAX))
+        );
+        assert_eq!(
+            Ok(i32::MIN),
+            i32::try_from(&Value::Integer(i32::MIN as i64))
+        );
         assert_eq!(Ok(false), bool::try_from(&Value::new(false)));
         assert_eq!(
             Ok("test".to_string()),
@@ -587,6 +1096,64 @@ mod value_mod_tests {
         );
     }
 
+    #[derive(Debug, PartialEq)]
+    enum Color {
+        Red,
+        Green,
+        Blue,
+    }
+
+    impl FromSymbol for Color {
+        fn from_symbol(symbol: &str) -> Option<Self> {
+            match symbol {
+                "red" => Some(Color::Red),
+                "green" => Some(Color::Green),
+                "blue" => Some(Color::Blue),
+                _ => None,
+            }
+        }
+    }
+
+    crate::impl_try_from_symbol!(Color);
+
+    #[test]
+    fn test_duration_conversion() {
+        let duration = Duration::from_millis(1500);
+        assert_eq!(Value::Num(1500.0), duration.to_value());
+        assert_eq!(Ok(duration), Duration::try_from(&duration.to_value()));
+        assert_eq!(
+            Ok(Duration::from_micros(250)),
+            Duration::try_from(&Value::Num(0.25))
+        );
+        assert!(Duration::try_from(&Value::Num(-1.0)).is_err());
+        assert!(Duration::try_from(&Value::new("abc")).is_err());
+    }
+
+    #[test]
+    fn test_as_int_loose() {
+        assert_eq!(Ok(1), Value::Boolean(true).as_int_loose());
+        assert_eq!(Ok(0), Value::Boolean(false).as_int_loose());
+        assert_eq!(Ok(42), Value::new(42).as_int_loose());
+        assert!(Value::new("abc").as_int_loose().is_err());
+        assert!(i32::try_from(&Value::Boolean(true)).is_err());
+    }
+
+    #[test]
+    fn test_try_from_symbol() {
+        assert_eq!(Ok(Color::Red), Color::try_from(&Value::new("red")));
+        assert_eq!(Ok(Color::Green), Color::try_from(&Value::new("green")));
+        assert_eq!(Ok(Color::Blue), Color::try_from(&Value::new("blue")));
+        let expected = TypeError::expected(format!(
+            "Value::Symbol naming a {}",
+            std::any::type_name::<Color>()
+        ));
+        assert_eq!(
+            Err(expected.got("Symbol yellow")),
+            Color::try_from(&Value::new("yellow"))
+        );
+        assert!(Color::try_from(&Value::new(1)).is_err());
+    }
+
     #[test]
     fn test_eq_num() {
         assert!(Value::Num(1.0).eq_num(1.0));
@@ -594,6 +1161,92 @@ mod value_mod_tests {
         assert!(!Value::Boolean(true).eq_num(1));
     }
 
+    #[test]
+    fn test_partial_cmp() {
+        assert!(Value::Num(1.0) < Value::Num(2.0));
+        assert!(Value::new("abc") < Value::new("abd"));
+        assert!(Value::new(vec![1, 2]) < Value::new(vec![1, 3]));
+        assert!(Value::new(vec![1, 2]) < Value::new(vec![1, 2, 0]));
+        assert!(Value::new(vec![2]) > Value::new(vec![1, 5]));
+        assert_eq!(
+            Some(Ordering::Equal),
+            Value::new(vec![1, 2]).partial_cmp(&Value::new(vec![1, 2]))
+        );
+        assert_eq!(
+            None,
+            Value::new(vec![1.to_value(), Value::new("a")])
+                .partial_cmp(&Value::new(vec![2.to_value(), 1.to_value()]))
+        );
+        assert_eq!(None, Value::new(1).partial_cmp(&Value::new("a")));
+        assert!(Value::Pointer(1) < Value::Pointer(3));
+        assert!(Value::Pointer(3) >= Value::Pointer(3));
+        assert_eq!(None, Value::Pointer(1).partial_cmp(&Value::Num(1.0)));
+        let pair = Value::cons(1.to_value(), Value::new(vec![2]));
+        assert!(pair < Value::new(vec![1, 3]));
+        assert!(Value::new(vec![1, 2, 0]) > pair);
+        assert_eq!(
+            None,
+            Value::cons(1.to_value(), 2.to_value()).partial_cmp(&pair)
+        );
+    }
+
+    #[test]
+    fn test_integer_overflow_modes() {
+        let (max, two) = (Value::Integer(i64::MAX), Value::Integer(2));
+        assert_eq!(
+            Err(MachineError::IntegerOverflow(format!("{} * 2", i64::MAX))),
+            max.mul_in(&two, IntegerOverflowMode::Checked)
+        );
+        assert_eq!(
+            Ok(Value::Integer(-2)),
+            max.mul_in(&two, IntegerOverflowMode::Wrapping)
+        );
+        assert_eq!(
+            Ok(Value::Integer(i64::MAX)),
+            max.mul_in(&two, IntegerOverflowMode::Saturating)
+        );
+        assert_eq!(
+            Ok(Value::Integer(i64::MIN)),
+            max.add_in(&Value::Integer(1), IntegerOverflowMode::Wrapping)
+        );
+        assert_eq!(
+            Ok(Value::Integer(i64::MIN)),
+            Value::Integer(i64::MIN).sub_in(&Value::Integer(1), IntegerOverflowMode::Saturating)
+        );
+        // The modes only apply to two integers.
+        assert_eq!(
+            Ok(Value::Num(3.5)),
+            Value::Integer(1).add_in(&Value::Num(2.5), IntegerOverflowMode::Checked)
+        );
+        assert!(matches!(
+            Value::Integer(1).add_in(&Value::Symbol("a".into()), IntegerOverflowMode::Wrapping),
+            Err(MachineError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_integer() {
+        assert_eq!(
+            "<Integer 3>",
+            format!("{:?}", Value::Integer(1) + Value::Integer(2))
+        );
+        assert_eq!("6", (Value::Integer(2) * Value::Integer(3)).to_string());
+        assert!((Value::Integer(6) / Value::Integer(3)).is_integer());
+        assert_eq!(Value::Num(0.5), Value::Integer(1) / Value::Integer(2));
+        assert!((Value::Integer(1) + Value::Num(0.5)).is_num());
+        assert!(!(Value::Integer(1) + Value::Num(0.5)).is_integer());
+        assert_eq!(
+            Value::Num(i64::MAX as f64 * 2.0),
+            Value::Integer(i64::MAX) * Value::Integer(2)
+        );
+        assert_eq!(Value::Integer(1), Value::Num(1.0));
+        assert_ne!(Value::Integer(1), Value::Num(1.5));
+        assert!(Value::Integer(1) < Value::Num(1.5));
+        assert_eq!(Value::Integer(-3), -Value::Integer(3));
+        assert_eq!(Ok(42), i32::try_from(&Value::Integer(42)));
+        assert_eq!("-7", Value::Integer(-7).to_scheme_string());
+    }
+
     #[test]
     fn test_arithmetic() {
         assert_eq!(3.to_value(), 1.to_value() + 2.to_value());
@@ -0,0 +1,95 @@
+//! Runs controller files in turn and reports the result of each one.
+//!
+//! Usage: `reg_machine <controller-file>...`
+//!
+//! The registers are the ones referenced in the controller text,
+//! and the operations are the standard ones installed by `make_machine_with_standard_ops`,
+//! from `math::standard_procedures` and `list::standard_procedures`.
+
+use std::collections::BTreeSet;
+use std::fs::read_to_string;
+use std::process;
+
+use reg_machine::{
+    make_machine_with_standard_ops,
+    parser::{parse, RMLNode},
+};
+
+/// Collects the register names referenced by the node.
+fn collect_registers(node: &RMLNode, names: &mut BTreeSet<String>) {
+    match node {
+        RMLNode::Assignment(reg_name, value) => {
+            names.insert(reg_name.to_string());
+            collect_registers(value, names);
+        }
+        RMLNode::Reg(reg_name) | RMLNode::Save(reg_name) | RMLNode::Restore(reg_name) => {
+            names.insert(reg_name.to_string());
+        }
+        RMLNode::Branch(node)
+        | RMLNode::GotoLabel(node)
+        | RMLNode::PerformOp(node)
+        | RMLNode::TestOp(node) => collect_registers(node, names),
+        RMLNode::Dispatch(node) => {
+            names.insert("argl".to_string());
+            names.insert("val".to_string());
+            collect_registers(node, names);
+        }
+        RMLNode::GotoIfEq(lhs, rhs, _) => {
+            collect_registers(lhs, names);
+            collect_registers(rhs, names);
+        }
+        RMLNode::IfFlag(consequent, alternative) => {
+            collect_registers(consequent, names);
+            collect_registers(alternative, names);
+        }
+        RMLNode::Operation(_, args) => args.iter().for_each(|arg| collect_registers(arg, names)),
+        _ => (),
+    }
+}
+
+fn run(path: &str) -> Result<Vec<String>, String> {
+    let controller_text = read_to_string(path).map_err(|e| e.to_string())?;
+    let nodes = parse(&controller_text).map_err(|e| e.to_string())?;
+    let mut names = BTreeSet::new();
+    nodes
+        .iter()
+        .for_each(|node| collect_registers(node, &mut names));
+    let register_names = names.iter().map(|s| s.as_str()).collect();
+    let mut machine = make_machine_with_standard_ops(register_names, &[], &controller_text)
+        .map_err(|e| e.to_string())?;
+    machine.start().map_err(|e| e.to_string())?;
+    names
+        .iter()
+        .map(|name| {
+            machine
+                .get_register_content(name)
+                .map(|value| format!("{} = {}", name, value))
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+fn main() {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("Usage: reg_machine <controller-file>...");
+        process::exit(2);
+    }
+    let mut failures = 0;
+    for path in paths.iter() {
+        match run(path) {
+            Ok(registers) => {
+                println!("{}: Done", path);
+                registers.iter().for_each(|line| println!("  {}", line));
+            }
+            Err(e) => {
+                failures += 1;
+                println!("{}: Failed: {}", path, e.trim_end());
+            }
+        }
+    }
+    println!("{} passed; {} failed", paths.len() - failures, failures);
+    if failures > 0 {
+        process::exit(1);
+    }
+}
@@ -1,4 +1,8 @@
-use crate::machine::value::Value;
+use crate::machine::{
+    errors::{MResult, MachineError, ProcedureError, TypeError},
+    procedure::Procedure,
+    value::{IntegerOverflowMode, Value},
+};
 
 pub fn addition(items: Vec<Value>) -> Value {
     items.into_iter().fold(Value::zero(), |acc, x| acc + x)
@@ -21,17 +25,46 @@ pub fn multiplication(items: Vec<Value>) -> Value {
     }
 }
 
-pub fn division(mut items: Vec<Value>) -> Value {
+/// Fails with `DivisionByZero` if any divisor is zero, or a `TypeError` if any item isn't a number.
+pub fn division(mut items: Vec<Value>) -> MResult<Value> {
+    checked_number("/", &items, 0)?;
+    for index in 1..items.len() {
+        checked_number("/", &items, index)?;
+    }
+    if items.len() == 1 {
+        items.insert(0, Value::one());
+    }
+    items[0].checked_div(&multiplication(items[1..].to_vec()))
+}
+
+/// Like `addition`, but an integer overflow is handled by `mode`.
+pub fn addition_in(items: Vec<Value>, mode: IntegerOverflowMode) -> MResult<Value> {
+    items
+        .iter()
+        .try_fold(Value::zero(), |acc, x| acc.add_in(x, mode))
+}
+
+/// Like `subtraction`, but an integer overflow is handled by `mode`.
+pub fn subtraction_in(mut items: Vec<Value>, mode: IntegerOverflowMode) -> MResult<Value> {
     if items.is_empty() {
-        panic!("[DIVISION] Requires at lease 1 item.");
-    } else if items[1..].contains(&Value::zero()) {
-        panic!("[DIVISION] Cannot divide by Value::Num(0.0).");
-    } else if items[0].eq_num(0) {
-        return Value::zero();
+        Err(ProcedureError::ArgsTooFew {
+            name: "-".into(),
+            expected: 1,
+            got: 0,
+        })?;
     } else if items.len() == 1 {
-        items.insert(0, Value::one());
+        items.insert(0, Value::zero());
     }
-    items[0].clone() / multiplication(items[1..].to_vec())
+    items[1..]
+        .iter()
+        .try_fold(items[0].clone(), |acc, x| acc.sub_in(x, mode))
+}
+
+/// Like `multiplication`, but an integer overflow is handled by `mode`.
+pub fn multiplication_in(items: Vec<Value>, mode: IntegerOverflowMode) -> MResult<Value> {
+    items
+        .iter()
+        .try_fold(Value::one(), |acc, x| acc.mul_in(x, mode))
 }
 
 fn comparison<T>(items: Vec<Value>, comparator: T) -> bool
@@ -45,40 +78,230 @@ where
     }
 }
 
-pub fn equal(items: Vec<Value>) -> bool {
-    comparison(items, Value::eq)
+pub fn equal(items: Vec<Value>) -> Value {
+    Value::Boolean(comparison(items, Value::eq))
+}
+
+/// Like `equal`, but the numbers are equal if they differ by at most `epsilon`.
+pub fn equal_within(items: Vec<Value>, epsilon: f64) -> Value {
+    Value::Boolean(comparison(items, |a, b| match (a.to_f64(), b.to_f64()) {
+        (Some(a), Some(b)) => (a - b).abs() <= epsilon,
+        _ => a == b,
+    }))
+}
+
+pub fn less_than(items: Vec<Value>) -> Value {
+    Value::Boolean(comparison(items, Value::lt))
+}
+
+pub fn greater_than(items: Vec<Value>) -> Value {
+    Value::Boolean(comparison(items, Value::gt))
+}
+
+pub fn less_than_or_equal_to(items: Vec<Value>) -> Value {
+    Value::Boolean(comparison(items, Value::le))
+}
+
+pub fn greater_than_or_equal_to(items: Vec<Value>) -> Value {
+    Value::Boolean(comparison(items, Value::ge))
+}
+
+fn number<'a>(op: &str, items: &'a [Value], index: usize) -> &'a Value {
+    match items.get(index) {
+        Some(n) if n.is_num() => n,
+        Some(other) => panic!("[{}] Requires a number, got {}.", op, other),
+        None => panic!("[{}] Requires {} items.", op, index + 1),
+    }
+}
+
+/// Like `number`, but fails with a `TypeError` or `ArgsTooFew` instead of panicking.
+fn checked_number<'a>(op: &str, items: &'a [Value], index: usize) -> MResult<&'a Value> {
+    match items.get(index) {
+        Some(n) if n.is_num() => Ok(n),
+        Some(other) => Err(TypeError::expected("Value::Num").got(other.to_string()))?,
+        None => Err(ProcedureError::ArgsTooFew {
+            name: op.into(),
+            expected: index + 1,
+            got: items.len(),
+        })?,
+    }
+}
+
+/// Rounds the number with `f`, an integer is already rounded.
+/// The result is an integer, unless it's out of the range of `i64`.
+fn rounding<T>(op: &str, items: Vec<Value>, f: T) -> Value
+where
+    T: Fn(f64) -> f64,
+{
+    match number(op, &items, 0) {
+        Value::Integer(i) => Value::Integer(*i),
+        n => {
+            let rounded = f(n.to_f64().unwrap());
+            if rounded.is_finite() && rounded.abs() < i64::MAX as f64 {
+                Value::Integer(rounded as i64)
+            } else {
+                Value::Num(rounded)
+            }
+        }
+    }
+}
+
+/// The remainder of dividing the first item by the second, with the sign of the first.
+/// Fails with `DivisionByZero` if the divisor is zero, or a `TypeError` if either item isn't a number.
+pub fn remainder(items: Vec<Value>) -> MResult<Value> {
+    let dividend = checked_number("rem", &items, 0)?;
+    let divisor = checked_number("rem", &items, 1)?;
+    if divisor.eq_num(0) {
+        return Err(MachineError::DivisionByZero(dividend.to_string()));
+    }
+    Ok(match (dividend, divisor) {
+        (Value::Integer(l), Value::Integer(r)) => Value::Integer(l.checked_rem(*r).unwrap_or(0)),
+        (l, r) => Value::Num(l.to_f64().unwrap() % r.to_f64().unwrap()),
+    })
 }
 
-pub fn less_than(items: Vec<Value>) -> bool {
-    comparison(items, Value::lt)
+/// The remainder of dividing the first item by the second, with the sign of the second.
+/// Fails like `remainder`.
+pub fn modulo(items: Vec<Value>) -> MResult<Value> {
+    let divisor = checked_number("modulo", &items, 1)?.clone();
+    let r = remainder(items)?;
+    if !r.eq_num(0) && (r < Value::zero()) != (divisor < Value::zero()) {
+        Ok(r + divisor)
+    } else {
+        Ok(r)
+    }
 }
 
-pub fn greater_than(items: Vec<Value>) -> bool {
-    comparison(items, Value::gt)
+pub fn abs(items: Vec<Value>) -> Value {
+    let n = number("ABS", &items, 0).clone();
+    if n < Value::zero() {
+        -n
+    } else {
+        n
+    }
 }
 
-pub fn less_than_or_equal_to(items: Vec<Value>) -> bool {
-    comparison(items, Value::le)
+pub fn floor(items: Vec<Value>) -> Value {
+    rounding("FLOOR", items, f64::floor)
 }
 
-pub fn greater_than_or_equal_to(items: Vec<Value>) -> bool {
-    comparison(items, Value::ge)
+pub fn ceiling(items: Vec<Value>) -> Value {
+    rounding("CEILING", items, f64::ceil)
+}
+
+/// Rounds to the nearest integer, and to the even one when halfway, as Scheme does.
+pub fn round(items: Vec<Value>) -> Value {
+    rounding("ROUND", items, f64::round_ties_even)
+}
+
+pub fn truncate(items: Vec<Value>) -> Value {
+    rounding("TRUNCATE", items, f64::trunc)
+}
+
+/// Whether the item isn't an integral number, the `noninteger?` operation,
+/// e.g. `2.5` or a symbol, but not `2` or `2.0`.
+pub fn noninteger(items: Vec<Value>) -> Value {
+    match items.first() {
+        Some(item) => Value::Boolean(!item.is_integral()),
+        None => panic!("[NONINTEGER?] Requires 1 items."),
+    }
+}
+
+/// Converts the number to a float point number, like Scheme's `exact->inexact`.
+pub fn exact_to_inexact(items: Vec<Value>) -> MResult<Value> {
+    let n = checked_number("exact->inexact", &items, 0)?;
+    Ok(Value::Num(n.to_f64().unwrap()))
+}
+
+/// Converts the number to an integer, like Scheme's `inexact->exact`.
+/// There are no rationals, so a float point number must be integral and within the range of `i64`.
+pub fn inexact_to_exact(items: Vec<Value>) -> MResult<Value> {
+    match checked_number("inexact->exact", &items, 0)? {
+        Value::Integer(i) => Ok(Value::Integer(*i)),
+        Value::Num(n) if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64 => {
+            Ok(Value::Integer(*n as i64))
+        }
+        n => Err(MachineError::ConvertError {
+            value: n.to_string(),
+            src: "Num".into(),
+            dst: "Integer".into(),
+        }),
+    }
+}
+
+/// The `=` operation, treating numbers as equal if they differ by at most `epsilon`
+/// when it's positive.
+pub fn equal_procedure(epsilon: f64) -> Procedure {
+    if epsilon > 0.0 {
+        Procedure::new("=", 0, move |items| equal_within(items, epsilon))
+    } else {
+        Procedure::new("=", 0, equal)
+    }
+}
+
+/// The `+`, `-` and `*` operations, handling an integer overflow by `mode`,
+/// or promoting the result to a float point number without one.
+pub fn arithmetic_procedures(mode: Option<IntegerOverflowMode>) -> Vec<Procedure> {
+    match mode {
+        Some(mode) => vec![
+            Procedure::fallible("+", 0, move |items| addition_in(items, mode)),
+            Procedure::fallible("-", 1, move |items| subtraction_in(items, mode)),
+            Procedure::fallible("*", 0, move |items| multiplication_in(items, mode)),
+        ],
+        None => vec![
+            Procedure::new("+", 0, addition),
+            Procedure::new("-", 1, subtraction),
+            Procedure::new("*", 0, multiplication),
+        ],
+    }
+}
+
+/// The standard arithmetic and comparison operations.
+pub fn standard_procedures() -> Vec<Procedure> {
+    let mut procedures = arithmetic_procedures(None);
+    procedures.extend(vec![
+        Procedure::fallible("/", 1, division),
+        equal_procedure(0.0),
+        Procedure::new("<", 0, less_than),
+        Procedure::new(">", 0, greater_than),
+        Procedure::new("<=", 0, less_than_or_equal_to),
+        Procedure::new(">=", 0, greater_than_or_equal_to),
+        Procedure::fallible("rem", 2, remainder),
+        Procedure::fallible("modulo", 2, modulo),
+        Procedure::new("abs", 1, abs),
+        Procedure::fallible("exact->inexact", 1, exact_to_inexact),
+        Procedure::fallible("inexact->exact", 1, inexact_to_exact),
+    ]);
+    procedures
 }
 
 #[cfg(test)]
 mod math_tests {
     use super::*;
-    use crate::machine::value::{ToValue, TryFromValue};
+    use crate::machine::{
+        errors::MachineError,
+        value::{ToValue, TryFromValue},
+    };
 
     #[test]
     fn test_addition() {
         let items = (1..=10).map(i32::to_value).collect();
         let sum = i32::try_from(&addition(items)).unwrap();
-        assert_eq!((1..=10).fold(0, |acc, x| acc + x), sum);
+        assert_eq!((1..=10).sum::<i32>(), sum);
         let sum = addition(Vec::<Value>::new());
         assert_eq!(Value::Num(0.0), sum);
     }
 
+    #[test]
+    fn test_equal_within() {
+        let items = vec![(0.1 + 0.2).to_value(), 0.3.to_value()];
+        assert_eq!(Value::Boolean(false), equal(items.clone()));
+        assert_eq!(Value::Boolean(true), equal_within(items, 1e-9));
+        let items = vec![1.to_value(), 1.05.to_value(), 1.1.to_value()];
+        assert_eq!(Value::Boolean(true), equal_within(items.clone(), 0.06));
+        assert_eq!(Value::Boolean(false), equal_within(items, 0.01));
+    }
+
     #[test]
     fn test_subtraction() {
         let difference = subtraction(vec![(-1).to_value()]);
@@ -93,16 +316,207 @@ mod math_tests {
         assert_eq!(Value::Num(1.0), multiplication(Vec::<Value>::new()));
         let items = (1..=10).map(i32::to_value).collect();
         let production = i32::try_from(&multiplication(items)).unwrap();
-        assert_eq!((1..=10).fold(1, |acc, x| acc * x), production);
+        assert_eq!((1..=10).product::<i32>(), production);
     }
 
     #[test]
     fn test_division() {
-        assert_eq!(Value::Num(0.5), division(vec![2.to_value()]));
+        assert_eq!(Ok(Value::Num(0.5)), division(vec![2.to_value()]));
         let items = (1..=10).rev().map(i32::to_value).collect();
         let expected = (1..10).map(|i| i as f64).rev().fold(10.0, |acc, x| acc / x);
-        let quotient = f64::try_from(&division(items)).unwrap();
+        let quotient = f64::try_from(&division(items).unwrap()).unwrap();
         let tolerance = (quotient - expected).abs();
         assert!(tolerance < 1e-20);
+        assert_eq!(
+            Ok(Value::zero()),
+            division(vec![0.to_value(), 2.to_value()])
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert_eq!(
+            Err(MachineError::DivisionByZero("1".into())),
+            division(vec![1.to_value(), 2.to_value(), 0.0.to_value()])
+        );
+        assert_eq!(
+            Err(MachineError::DivisionByZero("1".into())),
+            division(vec![0.to_value()])
+        );
+        assert!(matches!(
+            Value::new("a").checked_div(&1.to_value()),
+            Err(MachineError::TypeError(_))
+        ));
+        // A non-numeric divisor fails before the divisors are multiplied.
+        assert!(matches!(
+            division(vec![1.to_value(), 2.to_value(), Value::new("a")]),
+            Err(MachineError::TypeError(_))
+        ));
+        assert!(matches!(
+            division(vec![]),
+            Err(MachineError::ProcedureError(
+                ProcedureError::ArgsTooFew { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_comparison() {
+        let items = vec![1.to_value(), 1.to_value(), 1.to_value()];
+        assert_eq!(Value::Boolean(true), equal(items));
+        assert_eq!(
+            Value::Boolean(false),
+            equal(vec![1.to_value(), 2.to_value()])
+        );
+        let items = (1..=3).map(i32::to_value).collect();
+        assert_eq!(Value::Boolean(true), less_than(items));
+        let items = (1..=3).map(i32::to_value).collect();
+        assert_eq!(Value::Boolean(false), greater_than(items));
+        let items = vec![1.to_value(), 1.to_value(), 2.to_value()];
+        assert_eq!(Value::Boolean(true), less_than_or_equal_to(items));
+        let items = vec![2.to_value(), 2.to_value(), 1.to_value()];
+        assert_eq!(Value::Boolean(true), greater_than_or_equal_to(items));
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(Value::Num(2.0), floor(vec![2.7.to_value()]));
+        assert_eq!(Value::Num(3.0), ceiling(vec![2.1.to_value()]));
+        assert_eq!(Value::Num(2.0), round(vec![2.5.to_value()]));
+        assert_eq!(Value::Num(4.0), round(vec![3.5.to_value()]));
+        assert_eq!(Value::Num(3.0), round(vec![2.7.to_value()]));
+        assert_eq!(Value::Num(-2.0), truncate(vec![(-2.7).to_value()]));
+        assert!(floor(vec![2.7.to_value()]).is_integer());
+        assert!(round(vec![7.to_value()]).is_integer());
+    }
+
+    #[test]
+    fn test_noninteger() {
+        assert_eq!(Value::Boolean(false), noninteger(vec![3.to_value()]));
+        assert_eq!(Value::Boolean(false), noninteger(vec![3.0.to_value()]));
+        assert_eq!(Value::Boolean(true), noninteger(vec![2.5.to_value()]));
+        assert_eq!(Value::Boolean(true), noninteger(vec![f64::NAN.to_value()]));
+        assert_eq!(Value::Boolean(true), noninteger(vec![Value::new("q")]));
+    }
+
+    #[test]
+    fn test_exactness_conversions() {
+        let inexact = exact_to_inexact(vec![3.to_value()]).unwrap();
+        assert_eq!(Value::Num(3.0), inexact);
+        assert!(!inexact.is_integer());
+        let exact = inexact_to_exact(vec![3.0.to_value()]).unwrap();
+        assert_eq!(Value::Integer(3), exact);
+        assert!(exact.is_integer());
+        assert_eq!(
+            Err(MachineError::ConvertError {
+                value: "0.5".into(),
+                src: "Num".into(),
+                dst: "Integer".into(),
+            }),
+            inexact_to_exact(vec![0.5.to_value()])
+        );
+        assert!(inexact_to_exact(vec![f64::INFINITY.to_value()]).is_err());
+        assert_eq!(
+            Ok(Value::Integer(i64::MIN)),
+            inexact_to_exact(vec![(i64::MIN as f64).to_value()])
+        );
+        assert!(inexact_to_exact(vec![(i64::MAX as f64).to_value()]).is_err());
+        assert!(matches!(
+            inexact_to_exact(vec![Value::new("a")]),
+            Err(MachineError::TypeError(_))
+        ));
+        assert!(matches!(
+            exact_to_inexact(vec![Value::new("a")]),
+            Err(MachineError::TypeError(_))
+        ));
+        assert!(matches!(
+            exact_to_inexact(vec![]),
+            Err(MachineError::ProcedureError(
+                ProcedureError::ArgsTooFew { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_exactness_operations() {
+        let controller_text = r#"
+        (controller
+           (assign x (op exact->inexact) (const 3))
+           (assign y (op inexact->exact) (reg x)))
+        "#;
+        let mut m =
+            crate::make_machine(vec!["x", "y"], &standard_procedures(), controller_text).unwrap();
+        assert_eq!(Ok("Done"), m.start());
+        assert_eq!(Ok(Value::Num(3.0)), m.get_register_content("x"));
+        assert_eq!(Ok(Value::Integer(3)), m.get_register_content("y"));
+    }
+
+    #[test]
+    fn test_remainder_and_modulo_errors() {
+        assert_eq!(
+            Err(MachineError::DivisionByZero("13".into())),
+            remainder(vec![13.to_value(), 0.to_value()])
+        );
+        assert_eq!(
+            Err(MachineError::DivisionByZero("13".into())),
+            modulo(vec![13.to_value(), 0.0.to_value()])
+        );
+        for items in [
+            vec![Value::new("a"), 4.to_value()],
+            vec![13.to_value(), Value::new("a")],
+        ]
+        .iter()
+        {
+            assert!(matches!(
+                remainder(items.clone()),
+                Err(MachineError::TypeError(_))
+            ));
+            assert!(matches!(
+                modulo(items.clone()),
+                Err(MachineError::TypeError(_))
+            ));
+        }
+        assert_eq!(
+            Err(ProcedureError::ArgsTooFew {
+                name: "rem".into(),
+                expected: 2,
+                got: 1
+            }
+            .into()),
+            remainder(vec![13.to_value()])
+        );
+    }
+
+    #[test]
+    fn test_remainder_and_modulo() {
+        assert_eq!(
+            Value::Num(1.0),
+            remainder(vec![13.to_value(), 4.to_value()]).unwrap()
+        );
+        assert_eq!(
+            Value::Num(-1.0),
+            remainder(vec![(-13).to_value(), 4.to_value()]).unwrap()
+        );
+        assert_eq!(
+            Value::Num(3.0),
+            modulo(vec![(-13).to_value(), 4.to_value()]).unwrap()
+        );
+        assert_eq!(
+            Value::Num(-3.0),
+            modulo(vec![13.to_value(), (-4).to_value()]).unwrap()
+        );
+        assert_eq!(
+            Value::Num(0.0),
+            modulo(vec![12.to_value(), (-4).to_value()]).unwrap()
+        );
+        assert_eq!(Value::Num(2.5), abs(vec![(-2.5).to_value()]));
+        assert!(modulo(vec![(-13).to_value(), 4.to_value()])
+            .unwrap()
+            .is_integer());
+        assert_eq!(
+            Value::Num(1.5),
+            remainder(vec![5.5.to_value(), 2.to_value()]).unwrap()
+        );
+        assert!(abs(vec![(-2).to_value()]).is_integer());
     }
 }
@@ -5,9 +5,9 @@ use std::sync::Arc;
 
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_while, take_while1},
-    character::complete::{char, digit1, multispace0, not_line_ending},
-    combinator::{all_consuming, map, opt, recognize, verify},
+    bytes::complete::{tag, take_while1},
+    character::complete::{alphanumeric1, char, digit1, multispace1, not_line_ending, one_of},
+    combinator::{all_consuming, map, map_opt, not, opt, recognize, verify},
     error::{ErrorKind, ParseError},
     multi::many0,
     sequence::{delimited, pair, preceded, terminated, tuple},
@@ -17,8 +17,10 @@ use nom::{
 /// RML Value
 #[derive(Clone, Debug, PartialEq)]
 pub enum RMLValue {
+    Bool(bool),
+    Char(char),
     Float(f64),
-    Num(i32),
+    Num(i64),
     List(Vec<RMLValue>),
     Str(String),
     Symbol(String),
@@ -27,6 +29,12 @@ pub enum RMLValue {
 impl fmt::Display for RMLValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::Bool(v) => write!(f, "{}", if *v { "#t" } else { "#f" }),
+            Self::Char(c) => write!(f, "{}", char_literal(*c)),
+            Self::Float(v) if v.is_nan() => write!(f, "+nan.0"),
+            Self::Float(v) if v.is_infinite() => {
+                write!(f, "{}", if *v > 0.0 { "+inf.0" } else { "-inf.0" })
+            }
             Self::Float(v) => write!(f, "{}", v),
             Self::Num(v) => write!(f, "{}", v),
             Self::List(v) => write!(
@@ -37,7 +45,7 @@ impl fmt::Display for RMLValue {
                     .collect::<Vec<String>>()
                     .join(" ")
             ),
-            Self::Str(v) => write!(f, "\"{}\"", v),
+            Self::Str(v) => write!(f, "\"{}\"", escape_string(v)),
             Self::Symbol(v) => write!(f, "{}", v),
         }
     }
@@ -49,7 +57,10 @@ pub enum RMLNode {
     Assignment(String, Arc<RMLNode>),
     Branch(Arc<RMLNode>),
     Constant(RMLValue),
+    Dispatch(Arc<RMLNode>),
+    GotoIfEq(Arc<RMLNode>, Arc<RMLNode>, Arc<RMLNode>),
     GotoLabel(Arc<RMLNode>),
+    IfFlag(Arc<RMLNode>, Arc<RMLNode>),
     Label(String),
     List(Vec<RMLValue>),
     Operation(String, Vec<RMLNode>),
@@ -58,16 +69,70 @@ pub enum RMLNode {
     Restore(String),
     Save(String),
     Symbol(String),
+    TailCall(Arc<RMLNode>),
     TestOp(Arc<RMLNode>),
 }
 
+/// The kind of an `RMLNode`, to categorize a node without matching its contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Assign,
+    Branch,
+    Const,
+    Dispatch,
+    Goto,
+    GotoIfEq,
+    IfFlag,
+    Label,
+    List,
+    Operation,
+    Perform,
+    Reg,
+    Restore,
+    Save,
+    Symbol,
+    TailCall,
+    Test,
+}
+
+impl RMLNode {
+    pub fn kind(&self) -> NodeKind {
+        match self {
+            Self::Assignment(..) => NodeKind::Assign,
+            Self::Branch(_) => NodeKind::Branch,
+            Self::Constant(_) => NodeKind::Const,
+            Self::Dispatch(_) => NodeKind::Dispatch,
+            Self::GotoIfEq(..) => NodeKind::GotoIfEq,
+            Self::GotoLabel(_) => NodeKind::Goto,
+            Self::IfFlag(..) => NodeKind::IfFlag,
+            Self::Label(_) => NodeKind::Label,
+            Self::List(_) => NodeKind::List,
+            Self::Operation(..) => NodeKind::Operation,
+            Self::PerformOp(_) => NodeKind::Perform,
+            Self::Reg(_) => NodeKind::Reg,
+            Self::Restore(_) => NodeKind::Restore,
+            Self::Save(_) => NodeKind::Save,
+            Self::Symbol(_) => NodeKind::Symbol,
+            Self::TailCall(_) => NodeKind::TailCall,
+            Self::TestOp(_) => NodeKind::Test,
+        }
+    }
+}
+
 impl fmt::Display for RMLNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Assignment(reg, val) => write!(f, "(assign {} {})", reg, val),
             Self::Branch(label) => write!(f, "(branch {})", label),
             Self::Constant(value) => write!(f, "(const {})", value),
+            Self::Dispatch(reg) => write!(f, "(dispatch {})", reg),
+            Self::GotoIfEq(lhs, rhs, label) => {
+                write!(f, "(goto-if-eq {} {} {})", lhs, rhs, label)
+            }
             Self::GotoLabel(label) => write!(f, "(goto {})", label),
+            Self::IfFlag(consequent, alternative) => {
+                write!(f, "(if-flag {} {})", consequent, alternative)
+            }
             Self::Label(label) => write!(f, "(label {})", label),
             Self::List(v) => write!(
                 f,
@@ -77,12 +142,13 @@ impl fmt::Display for RMLNode {
                     .collect::<Vec<String>>()
                     .join(" ")
             ),
+            Self::Operation(op_name, args) if args.is_empty() => write!(f, "(op {})", op_name),
             Self::Operation(op_name, args) => write!(
                 f,
                 "(op {}) {}",
                 op_name,
                 args.iter()
-                    .map(|s| s.to_string())
+                    .map(operation_arg_text)
                     .collect::<Vec<String>>()
                     .join(" ")
             ),
@@ -92,7 +158,22 @@ impl fmt::Display for RMLNode {
             Self::Save(reg) => write!(f, "(save {})", reg),
             Self::TestOp(op) => write!(f, "(test {})", op),
             Self::Symbol(v) => write!(f, "{}", v),
+            Self::TailCall(label) => write!(f, "(tailcall {})", label),
+        }
+    }
+}
+
+/// Formats an operation argument, a nested operation is written as `(op <name> <input>...)`.
+fn operation_arg_text(arg: &RMLNode) -> String {
+    match arg {
+        RMLNode::Operation(op_name, args) => {
+            std::iter::once(format!("(op {}", op_name))
+                .chain(args.iter().map(operation_arg_text))
+                .collect::<Vec<String>>()
+                .join(" ")
+                + ")"
         }
+        other => other.to_string(),
     }
 }
 
@@ -103,8 +184,12 @@ pub enum RMLParseError<I: fmt::Debug> {
     BadNum,
     #[error("bad float point number")]
     BadFloatPoint,
+    #[error("bad escape sequence in string")]
+    BadEscape,
     #[error("bad symbol")]
     BadSymbol,
+    #[error("unknown character name")]
+    BadChar,
     #[error("unknown parser error")]
     ParseFailure { input: I, kind: ErrorKind },
 }
@@ -134,21 +219,50 @@ pub fn parse(input: &str) -> Result<Vec<RMLNode>, RMLParseError<&str>> {
         })?
 }
 
+/// Formats the parsed nodes back into a parenthesized controller text,
+/// with labels on their own lines. Parsing the result yields the same nodes.
+pub fn controller_text(nodes: &[RMLNode]) -> String {
+    let mut text = String::from("(");
+    for (i, node) in nodes.iter().enumerate() {
+        match node {
+            RMLNode::Symbol(label) if i == 0 => text.push_str(label),
+            RMLNode::Symbol(label) => text.push_str(&format!("\n {}", label)),
+            inst => text.push_str(&format!("\n   {}", inst)),
+        }
+    }
+    text.push(')');
+    text
+}
+
+/// Rewrites the parsed nodes by applying `f` to each of them in order,
+/// `f` may replace a node with any number of nodes, e.g. none to drop it.
+pub fn fold_nodes<F>(nodes: Vec<RMLNode>, f: F) -> Vec<RMLNode>
+where
+    F: FnMut(RMLNode) -> Vec<RMLNode>,
+{
+    nodes.into_iter().flat_map(f).collect()
+}
+
 /// A combinator that takes a parser `inner` and produces a parser that also
 /// consumes both leading and trailing whitespace, returning the output of `inner`.
 /// Ref: [Nom Recipes](https://github.com/Geal/nom/blob/4028bb3276339b231a4c60f5486e117a3c81e479/doc/nom_recipes.md#L21-L46)
 /// And ignore comments. `sce` stands for "spaces and comments eater".
-fn sce<'a, F: 'a, O, E: ParseError<&'a str>>(
-    inner: F,
-) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
+fn sce<'a, F, O>(inner: F) -> impl FnMut(&'a str) -> RMLResult<&'a str, O>
 where
-    F: FnMut(&'a str) -> IResult<&'a str, O, E>,
+    F: FnMut(&'a str) -> RMLResult<&'a str, O> + 'a,
 {
-    delimited(
-        terminated(multispace0, opt(pair(tag(";"), not_line_ending))),
-        terminated(inner, opt(pair(tag(";"), not_line_ending))),
-        terminated(multispace0, opt(pair(tag(";"), not_line_ending))),
-    )
+    delimited(junk, inner, junk)
+}
+
+/// Whitespace and comments, including the `#;` datum comment,
+/// which comments out the next datum, e.g. an instruction, a list or a symbol.
+fn junk(input: &str) -> RMLResult<&str, ()> {
+    let line_comment = recognize(pair(tag(";"), not_line_ending));
+    let datum_comment = recognize(preceded(tag("#;"), rml_value));
+    map(
+        many0(alt((multispace1, line_comment, datum_comment))),
+        |_| (),
+    )(input)
 }
 
 /// Multiple RML instructions
@@ -163,7 +277,11 @@ fn rml_instruction(input: &str) -> RMLResult<&str, RMLNode> {
         rml_label,
         rml_reg,
         rml_branch,
+        rml_goto_if_eq,
         rml_goto,
+        rml_tailcall,
+        rml_dispatch,
+        rml_if_flag,
         rml_save_and_restore,
         rml_apply_operation,
         rml_assign,
@@ -206,26 +324,138 @@ fn rml_symbol(input: &str) -> RMLResult<&str, RMLValue> {
 
 /// RML String
 ///
-/// Any characters wrapped in double quotes, except the double-quote and backslash.
+/// Any characters wrapped in double quotes, with the escapes
+/// `\n`, `\t`, `\r`, `\\` and `\"` decoded.
 fn rml_string(input: &str) -> RMLResult<&str, RMLValue> {
-    let parser = delimited(
-        char('"'),
-        take_while(|c| {
-            let cv = c as u32;
-            // 0x22: \", 0x5c: \\
-            (cv != 0x22) && (cv != 0x5c)
-        }),
-        char('"'),
-    );
-    map(parser, |s: &str| RMLValue::Str(s.into()))(input)
+    let (rest, _) = char('"')(input)?;
+    let mut string = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((&rest[i + 1..], RMLValue::Str(string))),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, 'r')) => string.push('\r'),
+                Some((_, '\\')) => string.push('\\'),
+                Some((_, '"')) => string.push('"'),
+                _ => return Err(nom::Err::Failure(RMLParseError::BadEscape)),
+            },
+            c => string.push(c),
+        }
+    }
+    Err(nom::Err::Error(RMLParseError::from_error_kind(
+        input,
+        ErrorKind::Char,
+    )))
+}
+
+/// The characters with names, written as `#\<name>`.
+const CHAR_NAMES: [(char, &str); 3] = [(' ', "space"), ('\n', "newline"), ('\t', "tab")];
+
+/// RML Character
+///
+/// Valid syntax: `#\<character>`, e.g. `#\a` and `#\(`,
+/// or `#\space`, `#\newline` and `#\tab` for the named characters.
+fn rml_char(input: &str) -> RMLResult<&str, RMLValue> {
+    let (rest, _) = tag("#\\")(input)?;
+    let mut chars = rest.chars();
+    let first = match chars.next() {
+        Some(c) => c,
+        None => {
+            return Err(nom::Err::Error(RMLParseError::from_error_kind(
+                input,
+                ErrorKind::Char,
+            )))
+        }
+    };
+    let rest = chars.as_str();
+    let end = rest
+        .find(|c: char| c.is_whitespace() || "()\";".contains(c))
+        .unwrap_or(rest.len());
+    if end == 0 {
+        return Ok((rest, RMLValue::Char(first)));
+    }
+    let name = &input[2..2 + first.len_utf8() + end];
+    match CHAR_NAMES.iter().find(|(_, n)| *n == name) {
+        Some((c, _)) => Ok((&rest[end..], RMLValue::Char(*c))),
+        None => Err(nom::Err::Failure(RMLParseError::BadChar)),
+    }
+}
+
+/// Writes the character as a literal parsed back by `rml_char`.
+pub(crate) fn char_literal(c: char) -> String {
+    match CHAR_NAMES.iter().find(|(named, _)| *named == c) {
+        Some((_, name)) => format!("#\\{}", name),
+        None => format!("#\\{}", c),
+    }
+}
+
+/// Escapes the string to be parsed back by `rml_string`, without the quotes.
+pub(crate) fn escape_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
 }
 
 /// RML Number
 ///
 /// Valid syntax: -?\d+
+/// Parsed into an `i64`, or a float point number if it's out of range.
 fn rml_number(input: &str) -> RMLResult<&str, RMLValue> {
     let (remain, num_string) = recognize(pair(opt(tag("-")), digit1))(input)?;
-    num_string.parse::<i32>().map_or_else(
+    if let Ok(n) = num_string.parse::<i64>() {
+        return Ok((remain, RMLValue::Num(n)));
+    }
+    num_string.parse::<f64>().map_or_else(
+        |_| Err(nom::Err::Failure(RMLParseError::BadNum)),
+        |f| Ok((remain, RMLValue::Float(f))),
+    )
+}
+
+/// RML Quote Shorthand
+///
+/// Valid syntax: `'<value>`, parsed as the list `(quote <value>)`.
+fn rml_quote(input: &str) -> RMLResult<&str, RMLValue> {
+    map(preceded(char('\''), rml_value), |value| {
+        RMLValue::List(vec![RMLValue::Symbol("quote".into()), value])
+    })(input)
+}
+
+/// RML Boolean
+///
+/// Valid syntax: `#t`, `#f`, `#true` or `#false`
+fn rml_bool(input: &str) -> RMLResult<&str, RMLValue> {
+    let parser = alt((tag("#true"), tag("#false"), tag("#t"), tag("#f")));
+    // Not a prefix of a symbol, like `#tag`.
+    let parser = terminated(parser, not(valid_symbol));
+    map(parser, |b: &str| RMLValue::Bool(b.starts_with("#t")))(input)
+}
+
+/// RML Number with a radix prefix
+///
+/// Valid syntax: `#x` for hexadecimal, `#o` for octal and `#b` for binary,
+/// followed by -?[0-9a-zA-Z]+, e.g. `#xff`, `#o17` and `#b1010`.
+fn rml_radix_number(input: &str) -> RMLResult<&str, RMLValue> {
+    let (remain, (radix, digits)) = pair(
+        preceded(char('#'), one_of("xXoObB")),
+        recognize(pair(opt(tag("-")), alphanumeric1)),
+    )(input)?;
+    let radix = match radix.to_ascii_lowercase() {
+        'x' => 16,
+        'o' => 8,
+        _ => 2,
+    };
+    i64::from_str_radix(digits, radix).map_or_else(
         |_| Err(nom::Err::Failure(RMLParseError::BadNum)),
         |n| Ok((remain, RMLValue::Num(n))),
     )
@@ -233,15 +463,37 @@ fn rml_number(input: &str) -> RMLResult<&str, RMLValue> {
 
 /// RML Float Point Number
 ///
-/// Valid syntax: -?\d+\.\d+
+/// Valid syntax: -?\d+(\.\d+)?([eE][+-]?\d+)?, with the fraction or the exponent part.
 fn rml_float(input: &str) -> RMLResult<&str, RMLValue> {
-    let (remain, float_num) = recognize(tuple((rml_number, char('.'), digit1)))(input)?;
+    let fraction = pair(char('.'), digit1);
+    let exponent = || tuple((one_of("eE"), opt(one_of("+-")), digit1));
+    let (remain, float_num) = recognize(tuple((
+        opt(tag("-")),
+        digit1,
+        alt((
+            recognize(pair(fraction, opt(exponent()))),
+            recognize(exponent()),
+        )),
+    )))(input)?;
     float_num.parse::<f64>().map_or_else(
         |_| Err(nom::Err::Failure(RMLParseError::BadFloatPoint)),
         |f| Ok((remain, RMLValue::Float(f))),
     )
 }
 
+/// RML Special Float Point Number
+///
+/// Valid syntax: `+inf.0`, `-inf.0`, `+nan.0` or `-nan.0`
+fn rml_special_float(input: &str) -> RMLResult<&str, RMLValue> {
+    // Matches the whole symbol, so a longer one like `+inf.0x` stays a symbol.
+    map_opt(valid_symbol, |s: &str| match s {
+        "+inf.0" => Some(RMLValue::Float(f64::INFINITY)),
+        "-inf.0" => Some(RMLValue::Float(f64::NEG_INFINITY)),
+        "+nan.0" | "-nan.0" => Some(RMLValue::Float(f64::NAN)),
+        _ => None,
+    })(input)
+}
+
 /// RML List
 ///
 /// Anything wrapped in double quotes.
@@ -252,7 +504,16 @@ fn rml_list(input: &str) -> RMLResult<&str, RMLValue> {
 
 pub fn rml_value(input: &str) -> RMLResult<&str, RMLValue> {
     sce(alt((
-        rml_float, rml_number, rml_symbol, rml_string, rml_list,
+        rml_quote,
+        rml_bool,
+        rml_char,
+        rml_radix_number,
+        rml_special_float,
+        rml_float,
+        rml_number,
+        rml_symbol,
+        rml_string,
+        rml_list,
     )))(input)
 }
 
@@ -320,15 +581,84 @@ fn rml_branch(input: &str) -> RMLResult<&str, RMLNode> {
 /// Valid syntax:
 /// - `(goto (label <label-name>))`
 /// - `(goto (reg <register-name>))`
+/// - `(goto (const <label-name>))`, the label is only checked when jumping.
 fn rml_goto(input: &str) -> RMLResult<&str, RMLNode> {
+    let const_label = verify(rml_const, |node| {
+        matches!(node, RMLNode::Constant(RMLValue::Symbol(_)))
+    });
     let parser = delimited(
         sce(char('(')),
-        preceded(sce(tag("goto")), alt((rml_label, rml_reg))),
+        preceded(sce(tag("goto")), alt((rml_label, rml_reg, const_label))),
         sce(char(')')),
     );
     map(parser, |l| RMLNode::GotoLabel(Arc::new(l)))(input)
 }
 
+/// RML Goto-If-Eq Instruction
+///
+/// A fused test and branch, which jumps to the label if the two inputs are equal.
+/// Otherwise, the controller continues with the next instruction.
+/// The flag register is left untouched.
+/// Valid syntax: `(goto-if-eq <input_1> <input_2> (label <label-name>))`
+fn rml_goto_if_eq(input: &str) -> RMLResult<&str, RMLNode> {
+    let parser = delimited(
+        sce(char('(')),
+        preceded(
+            sce(tag("goto-if-eq")),
+            tuple((operation_arg, operation_arg, rml_label)),
+        ),
+        sce(char(')')),
+    );
+    map(parser, |(lhs, rhs, label)| {
+        RMLNode::GotoIfEq(Arc::new(lhs), Arc::new(rhs), Arc::new(label))
+    })(input)
+}
+
+/// RML Tailcall Instruction
+///
+/// A `goto` annotated as a tail call, the machine checks that the stack depth
+/// is the same each time the label is tail called.
+/// Valid syntax: `(tailcall (label <label-name>))`
+fn rml_tailcall(input: &str) -> RMLResult<&str, RMLNode> {
+    let parser = delimited(
+        sce(char('(')),
+        preceded(sce(tag("tailcall")), rml_label),
+        sce(char(')')),
+    );
+    map(parser, |l| RMLNode::TailCall(Arc::new(l)))(input)
+}
+
+/// RML Dispatch Instruction
+///
+/// Calls the operation named by the symbol held in the register,
+/// with the items of the `argl` register as the arguments, and puts the result in `val`.
+/// Valid syntax: `(dispatch (reg <register-name>))`
+fn rml_dispatch(input: &str) -> RMLResult<&str, RMLNode> {
+    let parser = delimited(
+        sce(char('(')),
+        preceded(sce(tag("dispatch")), rml_reg),
+        sce(char(')')),
+    );
+    map(parser, |r| RMLNode::Dispatch(Arc::new(r)))(input)
+}
+
+/// RML If-Flag Instruction
+///
+/// A two-way branch based on the result of the previous test.
+/// If the test is true, the controller continues at the first label,
+/// otherwise at the second one.
+/// Valid syntax: `(if-flag (label <label-name>) (label <label-name>))`
+fn rml_if_flag(input: &str) -> RMLResult<&str, RMLNode> {
+    let parser = delimited(
+        sce(char('(')),
+        preceded(sce(tag("if-flag")), pair(rml_label, rml_label)),
+        sce(char(')')),
+    );
+    map(parser, |(consequent, alternative)| {
+        RMLNode::IfFlag(Arc::new(consequent), Arc::new(alternative))
+    })(input)
+}
+
 /// Operation name
 ///
 /// Valid syntax: `(op <operation-name>)`
@@ -342,9 +672,26 @@ fn operation_name(input: &str) -> RMLResult<&str, &str> {
 
 /// Operation arguments
 ///
-/// Valid syntax: `(reg <register-name>)` or `(const <constant-value>)`
+/// Valid syntax: `(reg <register-name>)`, `(const <constant-value>)`, `(label <label-name>)`
+/// or a nested operation, a label is passed as the index of its first instruction.
 fn operation_arg(input: &str) -> RMLResult<&str, RMLNode> {
-    sce(alt((rml_const, rml_reg)))(input)
+    sce(alt((rml_const, rml_reg, rml_label, nested_operation)))(input)
+}
+
+/// Nested Operation
+///
+/// An operation argument applying another operation, evaluated before the outer one.
+/// Valid syntax: `(op <operation-name> <input_1> ... <input_n>)`
+fn nested_operation(input: &str) -> RMLResult<&str, RMLNode> {
+    let parser = delimited(
+        sce(char('(')),
+        preceded(
+            sce(tag("op")),
+            pair(sce(valid_symbol), many0(operation_arg)),
+        ),
+        sce(char(')')),
+    );
+    map(parser, |(name, args)| RMLNode::Operation(name.into(), args))(input)
 }
 
 /// RML Operation
@@ -379,15 +726,19 @@ fn rml_apply_operation(input: &str) -> RMLResult<&str, RMLNode> {
 /// Valid syntax:
 /// - `(save <register-name>)`: save the contents of specified register on the stack.
 /// - `(restore <register-name>)`: pop the top item of stack, and save to the specified register.
+/// - `(push <register-name>)` and `(pop <register-name>)`: the same as `save` and `restore`.
 fn rml_save_and_restore(input: &str) -> RMLResult<&str, RMLNode> {
     let parser = delimited(
         sce(char('(')),
-        pair(sce(alt((tag("save"), tag("restore")))), valid_symbol),
+        pair(
+            sce(alt((tag("save"), tag("restore"), tag("push"), tag("pop")))),
+            valid_symbol,
+        ),
         sce(char(')')),
     );
     map(parser, |(inst, reg)| match inst {
-        "restore" => RMLNode::Restore(reg.into()),
-        "save" => RMLNode::Save(reg.into()),
+        "restore" | "pop" => RMLNode::Restore(reg.into()),
+        "save" | "push" => RMLNode::Save(reg.into()),
         _ => unreachable!(),
     })(input)
 }
@@ -475,6 +826,26 @@ mod parser_tests {
             rml_string(r#""1 + 1 = 2""#)
         );
         assert_eq!(Ok(("", RMLValue::Str(" ".into()))), rml_string(r#"" ""#));
+        assert_eq!(
+            Ok((
+                " rest",
+                RMLValue::Str("line1\nline2\t\r\\\"quoted\"".into())
+            )),
+            rml_string(r#""line1\nline2\t\r\\\"quoted\"" rest"#)
+        );
+        assert_eq!(
+            Err(nom::Err::Failure(RMLParseError::BadEscape)),
+            rml_string(r#""\q""#)
+        );
+        assert!(rml_string(r#""unterminated"#).is_err());
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let value = RMLValue::Str("say \"hi\"\n\tand \\ bye\r".into());
+        let text = value.to_string();
+        assert_eq!(r#""say \"hi\"\n\tand \\ bye\r""#, text);
+        assert_eq!(Ok(("", value)), rml_string(&text));
     }
 
     #[test]
@@ -484,6 +855,129 @@ mod parser_tests {
         assert_eq!(Ok(("_", RMLValue::Num(42))), rml_number("42_"));
         assert_eq!(Ok(("_2", RMLValue::Num(4))), rml_number("4_2"));
         assert!(rml_number("_42").is_err());
+        assert_eq!(
+            Ok(("", RMLValue::Num(3_000_000_000))),
+            rml_number("3000000000")
+        );
+        assert_eq!(
+            Ok(("", RMLValue::Num(i64::MIN))),
+            rml_number("-9223372036854775808")
+        );
+        assert_eq!(
+            Ok(("", RMLValue::Float(1e20))),
+            rml_number("100000000000000000000")
+        );
+    }
+
+    #[test]
+    fn test_rml_quote() {
+        let quote = |v| RMLValue::List(vec![RMLValue::Symbol("quote".into()), v]);
+        let sym = |s: &str| RMLValue::Symbol(s.into());
+        assert_eq!(Ok(("", quote(sym("a")))), rml_value("'a"));
+        assert_eq!(
+            Ok((
+                "",
+                quote(RMLValue::List(vec![sym("a"), sym("b"), sym("c")]))
+            )),
+            rml_value("'(a b c)")
+        );
+        assert_eq!(
+            Ok((
+                "",
+                quote(RMLValue::List(vec![sym("a"), quote(sym("b")), sym("c")]))
+            )),
+            rml_value("'(a 'b c)")
+        );
+        assert_eq!(Ok(("", quote(quote(sym("a"))))), rml_value("''a"));
+        assert_eq!(
+            "(quote (a (quote b) c))",
+            rml_value("'(a 'b c)").unwrap().1.to_string()
+        );
+    }
+
+    #[test]
+    fn test_rml_bool() {
+        assert_eq!(Ok(("", RMLValue::Bool(true))), rml_bool("#t"));
+        assert_eq!(Ok(("", RMLValue::Bool(false))), rml_bool("#f"));
+        assert_eq!(Ok(("", RMLValue::Bool(true))), rml_bool("#true"));
+        assert_eq!(Ok((")", RMLValue::Bool(false))), rml_bool("#false)"));
+        assert!(rml_bool("#tag").is_err());
+        assert_eq!(Ok(("", RMLValue::Symbol("#tag".into()))), rml_value("#tag"));
+        assert_eq!(
+            Ok(("", RMLNode::Constant(RMLValue::Bool(false)))),
+            rml_const("(const #f)")
+        );
+        for text in ["#t", "#f"].iter() {
+            let (_, value) = rml_bool(text).unwrap();
+            assert_eq!(*text, value.to_string());
+        }
+    }
+
+    #[test]
+    fn test_rml_char() {
+        assert_eq!(Ok(("", RMLValue::Char('a'))), rml_char("#\\a"));
+        assert_eq!(Ok((")", RMLValue::Char('('))), rml_char("#\\()"));
+        assert_eq!(Ok((" b", RMLValue::Char(' '))), rml_char("#\\space b"));
+        assert_eq!(Ok(("", RMLValue::Char('\n'))), rml_char("#\\newline"));
+        assert_eq!(Ok((")", RMLValue::Char('\t'))), rml_char("#\\tab)"));
+        assert_eq!(Ok(("", RMLValue::Char('λ'))), rml_char("#\\λ"));
+        assert_eq!(
+            Err(nom::Err::Failure(RMLParseError::BadChar)),
+            rml_char("#\\bell")
+        );
+        assert!(rml_char("#\\").is_err());
+        assert_eq!(
+            Ok(("", RMLNode::Constant(RMLValue::Char('x')))),
+            rml_const("(const #\\x)")
+        );
+        for text in ["#\\a", "#\\(", "#\\space", "#\\newline", "#\\tab"].iter() {
+            let (_, value) = rml_value(text).unwrap();
+            assert_eq!(*text, value.to_string());
+        }
+    }
+
+    #[test]
+    fn test_rml_radix_number() {
+        assert_eq!(Ok(("", RMLValue::Num(255))), rml_radix_number("#xff"));
+        assert_eq!(Ok(("", RMLValue::Num(255))), rml_radix_number("#XFF"));
+        assert_eq!(Ok(("", RMLValue::Num(15))), rml_radix_number("#o17"));
+        assert_eq!(Ok(("", RMLValue::Num(255))), rml_radix_number("#b11111111"));
+        assert_eq!(Ok(("", RMLValue::Num(-10))), rml_radix_number("#b-1010"));
+        assert_eq!(
+            Err(nom::Err::Failure(RMLParseError::BadNum)),
+            rml_radix_number("#b12")
+        );
+        assert!(rml_radix_number("ff").is_err());
+        assert_eq!(
+            Ok(("", RMLNode::Constant(RMLValue::Num(255)))),
+            rml_const("(const #xFF)")
+        );
+    }
+
+    #[test]
+    fn test_rml_special_float() {
+        assert_eq!(
+            Ok(("", RMLValue::Float(f64::INFINITY))),
+            rml_value("+inf.0")
+        );
+        assert_eq!(
+            Ok((")", RMLValue::Float(f64::NEG_INFINITY))),
+            rml_value("-inf.0)")
+        );
+        for nan in ["+nan.0", "-nan.0"].iter() {
+            match rml_value(nan) {
+                Ok(("", RMLValue::Float(f))) => assert!(f.is_nan()),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        for symbol in ["+inf.0x", "+inf.00", "inf.0", "-nan.0-"].iter() {
+            assert_eq!(
+                Ok(("", RMLValue::Symbol(symbol.to_string()))),
+                rml_value(symbol)
+            );
+        }
+        assert_eq!("-inf.0", RMLValue::Float(f64::NEG_INFINITY).to_string());
+        assert_eq!("+nan.0", RMLValue::Float(f64::NAN).to_string());
     }
 
     #[test]
@@ -492,6 +986,17 @@ mod parser_tests {
         assert_eq!(Ok(("", RMLValue::Float(-42.0))), rml_float("-42.0"));
         assert_eq!(Ok(("_", RMLValue::Float(42.0))), rml_float("42.0_"));
         assert!(rml_float("_42.0").is_err());
+        assert_eq!(Ok(("", RMLValue::Float(1e10))), rml_float("1e10"));
+        assert_eq!(Ok(("", RMLValue::Float(-2.5e-4))), rml_float("-2.5e-4"));
+        assert_eq!(Ok(("", RMLValue::Float(300.0))), rml_float("3E+2"));
+        assert_eq!(Ok(("", RMLValue::Float(6.022e23))), rml_float("6.022e23"));
+        assert_eq!(Ok(("e", RMLValue::Float(1.5))), rml_float("1.5e"));
+        assert!(rml_float("42").is_err());
+        assert!(rml_float("1e").is_err());
+        assert_eq!(
+            Ok(("", RMLNode::Constant(RMLValue::Float(1e10)))),
+            rml_const("(const 1e10)")
+        );
     }
 
     #[test]
@@ -612,6 +1117,62 @@ mod parser_tests {
             Ok(("", RMLNode::GotoLabel(Arc::new(RMLNode::Reg("a".into()))))),
             rml_goto("(goto (reg a))")
         );
+        let goto_const =
+            RMLNode::GotoLabel(Arc::new(RMLNode::Constant(RMLValue::Symbol("a".into()))));
+        assert_eq!(Ok(("", goto_const.clone())), rml_goto("(goto (const a))"));
+        assert_eq!("(goto (const a))", goto_const.to_string());
+        assert!(rml_goto("(goto (const 1))").is_err());
+        assert!(rml_goto("(goto (const \"a\"))").is_err());
+    }
+
+    #[test]
+    fn test_rml_tailcall() {
+        assert_eq!(
+            Ok(("", RMLNode::TailCall(Arc::new(RMLNode::Label("a".into()))))),
+            rml_tailcall("(tailcall (label a))")
+        );
+        assert!(rml_tailcall("(tailcall (reg a))").is_err());
+    }
+
+    #[test]
+    fn test_rml_dispatch() {
+        assert_eq!(
+            Ok(("", RMLNode::Dispatch(Arc::new(RMLNode::Reg("op".into()))))),
+            rml_dispatch("(dispatch (reg op))")
+        );
+        assert!(rml_dispatch("(dispatch (label op))").is_err());
+    }
+
+    #[test]
+    fn test_rml_goto_if_eq() {
+        assert_eq!(
+            Ok((
+                "",
+                RMLNode::GotoIfEq(
+                    Arc::new(RMLNode::Reg("a".into())),
+                    Arc::new(RMLNode::Constant(RMLValue::Num(0))),
+                    Arc::new(RMLNode::Label("done".into()))
+                )
+            )),
+            rml_goto_if_eq("(goto-if-eq (reg a) (const 0) (label done))")
+        );
+        assert!(rml_goto_if_eq("(goto-if-eq (reg a) (label done))").is_err());
+        assert!(rml_goto_if_eq("(goto-if-eq (reg a) (reg b) (reg c))").is_err());
+    }
+
+    #[test]
+    fn test_rml_if_flag() {
+        assert_eq!(
+            Ok((
+                "",
+                RMLNode::IfFlag(
+                    Arc::new(RMLNode::Label("a".into())),
+                    Arc::new(RMLNode::Label("b".into()))
+                )
+            )),
+            rml_if_flag("(if-flag (label a) (label b))")
+        );
+        assert!(rml_if_flag("(if-flag (label a))").is_err());
     }
 
     #[test]
@@ -622,6 +1183,40 @@ mod parser_tests {
         assert!(operation_name("(op 123)").is_err());
     }
 
+    #[test]
+    fn test_nested_operation() {
+        let nested = RMLNode::Operation(
+            "/".into(),
+            vec![RMLNode::Reg("x".into()), RMLNode::Reg("g".into())],
+        );
+        assert_eq!(
+            Ok(("", nested.clone())),
+            operation_arg("(op / (reg x) (reg g))")
+        );
+        let inst = RMLNode::Assignment(
+            "t".into(),
+            Arc::new(RMLNode::Operation(
+                "+".into(),
+                vec![RMLNode::Reg("g".into()), nested],
+            )),
+        );
+        assert_eq!(
+            Ok(vec![inst.clone()]),
+            parse("(assign t (op +) (reg g) (op / (reg x) (reg g)))")
+        );
+        assert_eq!(
+            "(assign t (op +) (reg g) (op / (reg x) (reg g)))",
+            inst.to_string()
+        );
+        assert_eq!(
+            Ok((
+                "",
+                RMLNode::Operation("f".into(), vec![RMLNode::Operation("g".into(), vec![])])
+            )),
+            operation("(op f) (op g)")
+        );
+    }
+
     #[test]
     fn test_operation_arg() {
         assert_eq!(Ok(("", RMLNode::Reg("a".into()))), operation_arg("(reg a)"));
@@ -633,6 +1228,10 @@ mod parser_tests {
             Ok(("", RMLNode::Constant(RMLValue::Symbol("abc".into())))),
             operation_arg("(const abc)")
         );
+        assert_eq!(
+            Ok(("", RMLNode::Label("done".into()))),
+            operation_arg("(label done)")
+        );
     }
 
     #[test]
@@ -703,6 +1302,14 @@ mod parser_tests {
             Ok(("", RMLNode::Restore("a".into()))),
             rml_save_and_restore("(restore a)")
         );
+        assert_eq!(
+            rml_save_and_restore("(save a)"),
+            rml_save_and_restore("(push a)")
+        );
+        assert_eq!(
+            rml_save_and_restore("(restore a)"),
+            rml_save_and_restore("(pop a)")
+        );
     }
 
     #[test]
@@ -787,6 +1394,118 @@ mod parser_tests {
         );
     }
 
+    #[test]
+    fn test_datum_comment() {
+        assert_eq!(
+            Ok((
+                "",
+                RMLValue::List(vec![
+                    RMLValue::Symbol("a".into()),
+                    RMLValue::Symbol("d".into())
+                ])
+            )),
+            rml_value("(a #;(b c) #; x d #;y)")
+        );
+        assert_eq!(
+            Ok(vec![
+                RMLNode::Symbol("controller".into()),
+                RMLNode::Assignment(
+                    "a".into(),
+                    Arc::new(RMLNode::Operation(
+                        "+".into(),
+                        vec![
+                            RMLNode::Reg("a".into()),
+                            RMLNode::Constant(RMLValue::Num(2))
+                        ]
+                    ))
+                ),
+                RMLNode::Symbol("done".into()),
+            ]),
+            parse(
+                r#"
+                (controller
+                   #;(assign a (op +) (reg a) (const 1)) ; disabled
+                   (assign a (op +) (reg a) #;(const 1) (const 2))
+                 #;skipped
+                 done)
+                "#
+            )
+        );
+    }
+
+    #[test]
+    fn test_node_kind() {
+        let nodes = parse(
+            r#"
+            (controller
+               (assign a (const 1))
+               (test (op =) (reg a) (const 1))
+               (branch (label done))
+               (save a)
+               (restore a)
+               (perform (op print) (reg a))
+               (goto (label controller))
+             done)
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                NodeKind::Symbol,
+                NodeKind::Assign,
+                NodeKind::Test,
+                NodeKind::Branch,
+                NodeKind::Save,
+                NodeKind::Restore,
+                NodeKind::Perform,
+                NodeKind::Goto,
+                NodeKind::Symbol,
+            ],
+            nodes.iter().map(RMLNode::kind).collect::<Vec<NodeKind>>()
+        );
+        assert_eq!(NodeKind::Reg, RMLNode::Reg("a".into()).kind());
+        assert_eq!(NodeKind::Const, RMLNode::Constant(RMLValue::Num(1)).kind());
+        assert_eq!(NodeKind::Label, RMLNode::Label("a".into()).kind());
+    }
+
+    #[test]
+    fn test_controller_text() {
+        let instructions = std::str::from_utf8(include_bytes!("../tests/rml_insts.scm")).unwrap();
+        let nodes = parse(instructions).unwrap();
+        let text = controller_text(&nodes);
+        assert!(text.starts_with("(controller\n   (perform (op print) "));
+        assert!(text.contains("\n fib-loop\n   (test (op <) (reg n) (const 2))"));
+        assert!(text.ends_with("\n done)"));
+        assert_eq!(Ok(nodes), parse(&text));
+        assert_eq!("()", controller_text(&[]));
+    }
+
+    #[test]
+    fn test_fold_nodes() {
+        let nop = RMLNode::List(vec![RMLValue::Symbol("nop".into())]);
+        let nodes = vec![
+            RMLNode::Symbol("start".into()),
+            nop.clone(),
+            RMLNode::Save("a".into()),
+            nop,
+            RMLNode::Restore("a".into()),
+        ];
+        let folded = fold_nodes(nodes, |node| match node {
+            RMLNode::List(v) if v == [RMLValue::Symbol("nop".into())] => vec![],
+            RMLNode::Save(reg) => vec![RMLNode::Save(reg.clone()), RMLNode::Save(reg)],
+            node => vec![node],
+        });
+        assert_eq!(
+            vec![
+                RMLNode::Symbol("start".into()),
+                RMLNode::Save("a".into()),
+                RMLNode::Save("a".into()),
+                RMLNode::Restore("a".into()),
+            ],
+            folded
+        );
+    }
+
     #[test]
     fn test_parse() {
         let instructions = std::str::from_utf8(include_bytes!("../tests/rml_insts.scm")).unwrap();
@@ -0,0 +1,33 @@
+use std::process::Command;
+
+fn controller(name: &str) -> String {
+    format!("{}/tests/controllers/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn test_run_multiple_controllers() {
+    let output = Command::new(env!("CARGO_BIN_EXE_reg_machine"))
+        .arg(controller("gcd.scm"))
+        .arg(controller("factorial.scm"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("gcd.scm: Done\n  a = 3\n"));
+    assert!(stdout.contains("factorial.scm: Done\n  c = 6\n  n = 5\n  p = 120\n"));
+    assert!(stdout.ends_with("2 passed; 0 failed\n"));
+}
+
+#[test]
+fn test_report_failed_controller() {
+    let output = Command::new(env!("CARGO_BIN_EXE_reg_machine"))
+        .arg(controller("gcd.scm"))
+        .arg(controller("missing.scm"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!output.status.success());
+    assert!(stdout.contains("gcd.scm: Done\n"));
+    assert!(stdout.contains("missing.scm: Failed: "));
+    assert!(stdout.ends_with("1 passed; 1 failed\n"));
+}
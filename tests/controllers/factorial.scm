@@ -0,0 +1,11 @@
+(controller
+   (assign n (const 5))
+   (assign p (const 1))
+   (assign c (const 1))
+ test-c
+   (test (op >) (reg c) (reg n))
+   (branch (label factorial-done))
+   (assign p (op *) (reg p) (reg c))
+   (assign c (op +) (reg c) (const 1))
+   (goto (label test-c))
+ factorial-done)
@@ -0,0 +1,11 @@
+(controller
+   (assign a (const 1023))
+   (assign b (const 27))
+ test-b
+   (test (op =) (reg b) (const 0))
+   (branch (label gcd-done))
+   (assign t (op rem) (reg a) (reg b))
+   (assign a (reg b))
+   (assign b (reg t))
+   (goto (label test-b))
+ gcd-done)